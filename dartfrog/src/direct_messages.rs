@@ -0,0 +1,328 @@
+use dartfrog_lib::*;
+use hyperware_process_lib::Address;
+use serde::{Deserialize, Serialize};
+
+use crate::{update_all_consumers, DartfrogState, HubInput, HubOutput};
+
+// Every peer gets the same schedule, whether or not its build sends acks.
+// Retries back off up to the max delay, and a message nobody acked by the
+// max age has failed.
+const RETRY_BASE_DELAY: u64 = 30; // seconds
+const RETRY_MAX_DELAY: u64 = 6 * 60 * 60;
+const MAX_DELIVERY_AGE: u64 = 3 * 24 * 60 * 60;
+// delivered messages are kept this long in case a read receipt follows
+const DELIVERED_RETENTION: u64 = 24 * 60 * 60;
+// failed messages can be retried from the ui until they're this old
+const FAILED_RETENTION: u64 = 7 * 24 * 60 * 60;
+// how long a typing indicator lasts unless it's renewed
+const TYPING_TIMEOUT: u64 = 6; // seconds
+// typing pokes from the ui are forwarded at most this often per peer
+const TYPING_RESEND_INTERVAL: u64 = 3;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum DirectMessageInput {
+    // local: try a failed message again
+    RetryMessage(String),
+    // local
+    SetPrivacy(DirectMessagePrivacy),
+    // local: the user is typing to this node. remote: the sender is typing to us
+    Typing(String),
+    // remote: the peer stored the message with this id
+    Ack(String),
+    // remote: the peer has seen these messages
+    Read(Vec<String>),
+}
+
+// Like ActivitySetting, but for read receipts and typing indicators. Private
+// works both ways: nothing is sent, and nothing from peers is shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DirectMessagePrivacy {
+    Public,
+    Private,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum DirectMessageOutput {
+    DeliveryList(Vec<DeliveryStatus>),
+    Delivery(DeliveryStatus),
+    // the message is no longer tracked, and its last known state stands
+    DeliveryCleared(String),
+    Privacy(DirectMessagePrivacy),
+    Typing {
+        node: String,
+        expires_at: u64,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeliveryState {
+    Pending,
+    Delivered,
+    Read,
+    Failed,
+}
+
+// DirectMessage belongs to dartfrog_lib, so the delivery state of messages we
+// sent is tracked next to the message stores, keyed by message id
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Delivery {
+    pub node: String,
+    pub state: DeliveryState,
+    pub attempts: u32,
+    pub first_attempt: u64,
+    pub last_attempt: u64,
+    pub next_attempt: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeliveryStatus {
+    pub node: String,
+    pub message_id: String,
+    pub state: DeliveryState,
+}
+
+impl Delivery {
+    pub fn new(node: String, now: u64) -> Self {
+        Delivery {
+            node,
+            state: DeliveryState::Pending,
+            attempts: 1,
+            first_attempt: now,
+            last_attempt: now,
+            next_attempt: now + retry_delay(1),
+        }
+    }
+
+    pub fn record_attempt(&mut self, now: u64) {
+        self.attempts += 1;
+        self.last_attempt = now;
+        self.next_attempt = now + retry_delay(self.attempts);
+    }
+
+    // starts the schedule over, for a retry the user asked for
+    pub fn restart(&mut self, now: u64) {
+        self.attempts = 0;
+        self.first_attempt = now;
+    }
+
+    // not worth resending just because the peer was heard from
+    pub fn recently_sent(&self, now: u64) -> bool {
+        self.last_attempt + RETRY_BASE_DELAY > now
+    }
+
+    pub fn expired(&self, now: u64) -> bool {
+        self.first_attempt + MAX_DELIVERY_AGE <= now
+    }
+
+    pub fn status(&self, message_id: &str) -> DeliveryStatus {
+        DeliveryStatus {
+            node: self.node.clone(),
+            message_id: message_id.to_string(),
+            state: self.state,
+        }
+    }
+}
+
+fn retry_delay(attempts: u32) -> u64 {
+    RETRY_BASE_DELAY
+        .saturating_mul(1 << attempts.min(16))
+        .min(RETRY_MAX_DELAY)
+}
+
+pub fn delivery_list(state: &DartfrogState) -> HubOutput {
+    let statuses = state.deliveries.iter()
+        .map(|(message_id, delivery)| delivery.status(message_id))
+        .collect();
+    HubOutput::DirectMessages(DirectMessageOutput::DeliveryList(statuses))
+}
+
+fn set_delivery_state(state: &mut DartfrogState, message_id: &str, new_state: DeliveryState) -> anyhow::Result<()> {
+    let Some(delivery) = state.deliveries.get_mut(message_id) else {
+        return Ok(());
+    };
+    if delivery.state == new_state {
+        return Ok(());
+    }
+    delivery.state = new_state;
+    let status = delivery.status(message_id);
+    // nothing comes after a read receipt
+    if new_state == DeliveryState::Read {
+        state.deliveries.remove(message_id);
+    }
+    update_all_consumers(state, HubOutput::DirectMessages(DirectMessageOutput::Delivery(status)))?;
+    state.save();
+    Ok(())
+}
+
+fn clear_delivery(state: &mut DartfrogState, message_id: &str) -> anyhow::Result<()> {
+    if state.deliveries.remove(message_id).is_none() {
+        return Ok(());
+    }
+    update_all_consumers(state, HubOutput::DirectMessages(DirectMessageOutput::DeliveryCleared(message_id.to_string())))
+}
+
+pub fn send_to_peer(node: &str, message: &DirectMessage) -> anyhow::Result<()> {
+    let remote_poke = RemoteDirectMessagePoke::SendMessage(message.id.clone(), message.contents.clone());
+    let address = get_server_address(node);
+    poke(&address, DartfrogInput::RemoteDirectMessages(remote_poke))
+}
+
+fn resend(state: &mut DartfrogState, message_id: &str, now: u64) -> anyhow::Result<()> {
+    let Some(node) = state.deliveries.get(message_id).map(|delivery| delivery.node.clone()) else {
+        return Ok(());
+    };
+    let message = state.messages.get(&node)
+        .and_then(|store| store.history.iter().find(|message| message.id == message_id))
+        .cloned();
+    let Some(message) = message else {
+        // the message itself is gone, so there is nothing left to deliver
+        state.deliveries.remove(message_id);
+        return Ok(());
+    };
+    if let Some(delivery) = state.deliveries.get_mut(message_id) {
+        delivery.record_attempt(now);
+    }
+    send_to_peer(&node, &message)
+}
+
+pub fn handle_input(
+    our: &Address,
+    state: &mut DartfrogState,
+    source: &Address,
+    input: DirectMessageInput,
+) -> anyhow::Result<()> {
+    match input {
+        DirectMessageInput::RetryMessage(message_id) => {
+            if source.node != our.node {
+                return Ok(());
+            }
+            let is_failed = state.deliveries.get(&message_id)
+                .is_some_and(|delivery| delivery.state == DeliveryState::Failed);
+            if !is_failed {
+                return Ok(());
+            }
+            if let Some(delivery) = state.deliveries.get_mut(&message_id) {
+                delivery.restart(get_now());
+            }
+            resend(state, &message_id, get_now())?;
+            set_delivery_state(state, &message_id, DeliveryState::Pending)?;
+        }
+        DirectMessageInput::SetPrivacy(privacy) => {
+            if source.node != our.node {
+                return Ok(());
+            }
+            state.dm_privacy = privacy;
+            update_all_consumers(state, HubOutput::DirectMessages(DirectMessageOutput::Privacy(privacy)))?;
+            state.save();
+        }
+        DirectMessageInput::Typing(node) => {
+            if state.dm_privacy == DirectMessagePrivacy::Private {
+                return Ok(());
+            }
+            let now = get_now();
+            if source.node == our.node {
+                let recently_sent = state.typing_sent.get(&node)
+                    .is_some_and(|&sent| sent + TYPING_RESEND_INTERVAL > now);
+                if recently_sent || node == our.node {
+                    return Ok(());
+                }
+                state.typing_sent.insert(node.clone(), now);
+                let address = get_server_address(&node);
+                poke(&address, HubInput::DirectMessages(DirectMessageInput::Typing(our.node.clone())))?;
+            } else if state.messages.contains_key(&source.node) {
+                // only shown for existing conversations, and always under the real sender
+                let typing = DirectMessageOutput::Typing {
+                    node: source.node.clone(),
+                    expires_at: now + TYPING_TIMEOUT,
+                };
+                update_all_consumers(state, HubOutput::DirectMessages(typing))?;
+            }
+        }
+        DirectMessageInput::Ack(message_id) => {
+            // only the node a message went to can confirm it
+            let is_recipient = state.deliveries.get(&message_id)
+                .is_some_and(|delivery| delivery.node == source.node);
+            if !is_recipient {
+                return Ok(());
+            }
+            // read messages are no longer tracked, so this can't undo a read receipt
+            set_delivery_state(state, &message_id, DeliveryState::Delivered)?;
+        }
+        DirectMessageInput::Read(message_ids) => {
+            if state.dm_privacy == DirectMessagePrivacy::Private {
+                return Ok(());
+            }
+            for message_id in message_ids {
+                let is_recipient = state.deliveries.get(&message_id)
+                    .is_some_and(|delivery| delivery.node == source.node);
+                if is_recipient {
+                    set_delivery_state(state, &message_id, DeliveryState::Read)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// Tells a peer we've seen its messages, unless read receipts are off
+pub fn send_read_receipts(state: &DartfrogState, node: &str, message_ids: Vec<String>) -> anyhow::Result<()> {
+    if state.dm_privacy == DirectMessagePrivacy::Private || message_ids.is_empty() {
+        return Ok(());
+    }
+    let address = get_server_address(node);
+    poke(&address, HubInput::DirectMessages(DirectMessageInput::Read(message_ids)))
+}
+
+pub fn acknowledge(node: &str, message_id: String) -> anyhow::Result<()> {
+    let address = get_server_address(node);
+    poke(&address, HubInput::DirectMessages(DirectMessageInput::Ack(message_id)))
+}
+
+// Hearing from a peer means it's online, so anything still waiting for it goes
+// out now, unless it was only just sent
+pub fn resend_pending_to(state: &mut DartfrogState, node: &str) -> anyhow::Result<()> {
+    let now = get_now();
+    let pending: Vec<String> = state.deliveries.iter()
+        .filter(|(_, delivery)| delivery.node == node && delivery.state == DeliveryState::Pending)
+        .filter(|(_, delivery)| !delivery.recently_sent(now) && !delivery.expired(now))
+        .map(|(message_id, _)| message_id.clone())
+        .collect();
+    for message_id in pending {
+        resend(state, &message_id, now)?;
+    }
+    Ok(())
+}
+
+// Runs on the hub timer. Retries whatever is due, backing off between
+// attempts, and marks messages failed once they're too old. Delivered and
+// failed messages are dropped once nothing more is expected of them.
+pub fn retry_due(state: &mut DartfrogState) -> anyhow::Result<()> {
+    let now = get_now();
+    let settled: Vec<String> = state.deliveries.iter()
+        .filter(|(_, delivery)| match delivery.state {
+            DeliveryState::Delivered => delivery.last_attempt + DELIVERED_RETENTION <= now,
+            DeliveryState::Failed => delivery.last_attempt + FAILED_RETENTION <= now,
+            _ => false,
+        })
+        .map(|(message_id, _)| message_id.clone())
+        .collect();
+    let due: Vec<(String, bool)> = state.deliveries.iter()
+        .filter(|(_, delivery)| delivery.state == DeliveryState::Pending && delivery.next_attempt <= now)
+        .map(|(message_id, delivery)| (message_id.clone(), delivery.expired(now)))
+        .collect();
+    if settled.is_empty() && due.is_empty() {
+        return Ok(());
+    }
+    for message_id in settled {
+        clear_delivery(state, &message_id)?;
+    }
+    for (message_id, expired) in due {
+        if expired {
+            set_delivery_state(state, &message_id, DeliveryState::Failed)?;
+        } else {
+            resend(state, &message_id, now)?;
+        }
+    }
+    state.save();
+    Ok(())
+}
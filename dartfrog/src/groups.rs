@@ -0,0 +1,478 @@
+use std::collections::BTreeSet;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use dartfrog_lib::*;
+use hyperware_process_lib::Address;
+use serde::{Deserialize, Serialize};
+
+use crate::direct_messages::Delivery;
+use crate::{update_all_consumers, DartfrogState, HubInput, HubOutput};
+
+const MAX_GROUP_MEMBERS: usize = 32;
+const MAX_GROUP_NAME_LENGTH: usize = 64;
+// invites past this are dropped until some are accepted or declined
+const MAX_GROUP_INVITES: usize = 32;
+
+// Group conversations live beside the one-to-one message stores. The
+// RemoteDirectMessagePoke in dartfrog_lib has no room for a conversation id,
+// so group traffic between hubs uses these pokes instead.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum GroupInput {
+    // local
+    CreateGroup { name: String, members: Vec<String> },
+    SendMessage { conversation_id: String, contents: String },
+    Invite { conversation_id: String, node: String },
+    Leave(String),
+    ClearUnread(String),
+    AcceptInvite(String),
+    // also tells the members, so they stop sending to us
+    DeclineInvite(String),
+    // remote: the full conversation as a member sees it, sent on every membership change
+    Membership(GroupInfo),
+    // remote
+    Message { conversation_id: String, message_id: String, contents: String },
+    // remote: the sender left the conversation
+    MemberLeft(String),
+    // remote: the member handled this, so the sender can stop retrying it
+    Ack(GroupAck),
+}
+
+// Names a remote group poke without its contents
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum GroupAck {
+    Message { conversation_id: String, message_id: String },
+    Membership { conversation_id: String, members: BTreeSet<String> },
+    MemberLeft(String),
+}
+
+// A group poke waiting on one member's ack, retried on the direct message schedule
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupDelivery {
+    pub delivery: Delivery,
+    pub input: GroupInput,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum GroupOutput {
+    ConversationList(Vec<GroupConversation>),
+    Conversation(GroupConversation),
+    LeftConversation(String),
+    InviteList(Vec<GroupInvite>),
+    Invite(GroupInvite),
+    // accepted or declined
+    InviteRemoved(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupInfo {
+    pub id: String,
+    pub name: String,
+    pub members: BTreeSet<String>,
+}
+
+// A group we were added to by another member. Nothing is shown or kept from it
+// until the user accepts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupInvite {
+    pub info: GroupInfo,
+    pub from: String,
+    pub received_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupConversation {
+    pub id: String,
+    pub name: String,
+    pub members: BTreeSet<String>,
+    pub history: Vec<GroupMessage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupMessage {
+    pub id: String,
+    pub from: String,
+    pub is_unread: bool,
+    pub contents: String,
+    pub time_received: u64,
+}
+
+impl GroupConversation {
+    fn info(&self) -> GroupInfo {
+        GroupInfo {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            members: self.members.clone(),
+        }
+    }
+}
+
+pub fn conversation_list(state: &DartfrogState) -> HubOutput {
+    HubOutput::GroupMessages(GroupOutput::ConversationList(state.groups.values().cloned().collect()))
+}
+
+pub fn invite_list(state: &DartfrogState) -> HubOutput {
+    HubOutput::GroupMessages(GroupOutput::InviteList(state.group_invites.values().cloned().collect()))
+}
+
+fn new_id() -> String {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos()
+        .to_string()
+}
+
+fn ack_for(input: &GroupInput) -> Option<GroupAck> {
+    match input {
+        GroupInput::Message { conversation_id, message_id, .. } => Some(GroupAck::Message {
+            conversation_id: conversation_id.clone(),
+            message_id: message_id.clone(),
+        }),
+        GroupInput::Membership(info) => Some(GroupAck::Membership {
+            conversation_id: info.id.clone(),
+            members: info.members.clone(),
+        }),
+        GroupInput::MemberLeft(conversation_id) => Some(GroupAck::MemberLeft(conversation_id.clone())),
+        _ => None,
+    }
+}
+
+fn poke_member(member: &str, input: &GroupInput) -> anyhow::Result<()> {
+    let address = get_server_address(member);
+    poke(&address, HubInput::GroupMessages(input.clone()))
+}
+
+// Queues the poke for every other member. The caller saves before sending, so
+// a failed send is still retried.
+fn queue_for_members(our: &Address, state: &mut DartfrogState, members: &BTreeSet<String>, input: &GroupInput) {
+    let Some(ack) = ack_for(input) else {
+        return;
+    };
+    let now = get_now();
+    for member in members {
+        if member == &our.node {
+            continue;
+        }
+        if let GroupAck::Membership { conversation_id, .. } = &ack {
+            // each membership poke is the full list, so it replaces any still waiting
+            state.group_deliveries.retain(|(node, pending), _| {
+                node != member || !matches!(pending, GroupAck::Membership { conversation_id: id, .. } if id == conversation_id)
+            });
+        }
+        state.group_deliveries.insert((member.clone(), ack.clone()), GroupDelivery {
+            delivery: Delivery::new(member.clone(), now),
+            input: input.clone(),
+        });
+    }
+}
+
+// Tries every member even if one send fails, and reports the last failure
+fn poke_members(our: &Address, members: &BTreeSet<String>, input: &GroupInput) -> anyhow::Result<()> {
+    let mut result = Ok(());
+    for member in members {
+        if member == &our.node {
+            continue;
+        }
+        if let Err(e) = poke_member(member, input) {
+            result = Err(e);
+        }
+    }
+    result
+}
+
+fn resend(state: &mut DartfrogState, key: &(String, GroupAck), now: u64) -> anyhow::Result<()> {
+    let Some(pending) = state.group_deliveries.get_mut(key) else {
+        return Ok(());
+    };
+    pending.delivery.record_attempt(now);
+    poke_member(&key.0, &pending.input)
+}
+
+// Runs on the hub timer next to the direct message retries, on the same
+// schedule. Pokes no member acked in time are dropped.
+pub fn retry_due(state: &mut DartfrogState) -> anyhow::Result<()> {
+    let now = get_now();
+    let due: Vec<(String, GroupAck)> = state.group_deliveries.iter()
+        .filter(|(_, pending)| pending.delivery.next_attempt <= now)
+        .map(|(key, _)| key.clone())
+        .collect();
+    if due.is_empty() {
+        return Ok(());
+    }
+    for key in due {
+        if state.group_deliveries[&key].delivery.expired(now) {
+            state.group_deliveries.remove(&key);
+        } else {
+            resend(state, &key, now)?;
+        }
+    }
+    state.save();
+    Ok(())
+}
+
+// Like direct_messages::resend_pending_to, for group pokes
+pub fn resend_pending_to(state: &mut DartfrogState, node: &str) -> anyhow::Result<()> {
+    let now = get_now();
+    let pending: Vec<(String, GroupAck)> = state.group_deliveries.iter()
+        .filter(|((member, _), pending)| member == node && !pending.delivery.recently_sent(now) && !pending.delivery.expired(now))
+        .map(|(key, _)| key.clone())
+        .collect();
+    for key in pending {
+        resend(state, &key, now)?;
+    }
+    Ok(())
+}
+
+// Holds a membership for a group we're not in as an invite. While it's
+// pending, later memberships from its members replace it, so accepting joins
+// the current list.
+fn receive_invite(state: &mut DartfrogState, source: &Address, info: GroupInfo) -> anyhow::Result<()> {
+    let info = GroupInfo {
+        name: info.name.chars().take(MAX_GROUP_NAME_LENGTH).collect(),
+        ..info
+    };
+    match state.group_invites.get(&info.id) {
+        Some(invite) => {
+            if !invite.info.members.contains(&source.node) {
+                return Ok(());
+            }
+        }
+        None => {
+            if state.group_invites.len() >= MAX_GROUP_INVITES {
+                return Ok(());
+            }
+        }
+    }
+    let invite = GroupInvite {
+        info,
+        from: source.node.clone(),
+        received_at: get_now(),
+    };
+    state.group_invites.insert(invite.info.id.clone(), invite.clone());
+    state.save();
+    update_all_consumers(state, HubOutput::GroupMessages(GroupOutput::Invite(invite)))
+}
+
+fn publish_conversation(state: &DartfrogState, conversation_id: &str) -> anyhow::Result<()> {
+    if let Some(conversation) = state.groups.get(conversation_id) {
+        update_all_consumers(state, HubOutput::GroupMessages(GroupOutput::Conversation(conversation.clone())))?;
+    }
+    Ok(())
+}
+
+pub fn handle_input(
+    our: &Address,
+    state: &mut DartfrogState,
+    source: &Address,
+    input: GroupInput,
+) -> anyhow::Result<()> {
+    // remote pokes are acked even when ignored or repeated, so the sender stops
+    let ack = if source.node == our.node { None } else { ack_for(&input) };
+    apply_input(our, state, source, input)?;
+    if let Some(ack) = ack {
+        poke_member(&source.node, &GroupInput::Ack(ack))?;
+    }
+    Ok(())
+}
+
+fn apply_input(
+    our: &Address,
+    state: &mut DartfrogState,
+    source: &Address,
+    input: GroupInput,
+) -> anyhow::Result<()> {
+    let is_local = source.node == our.node;
+    match input {
+        GroupInput::CreateGroup { name, members } => {
+            if !is_local {
+                return Ok(());
+            }
+            let mut members: BTreeSet<String> = members.into_iter().collect();
+            members.insert(our.node.clone());
+            if members.len() < 2 || members.len() > MAX_GROUP_MEMBERS {
+                return Ok(());
+            }
+            let conversation = GroupConversation {
+                // prefixed with our node so ids from different creators can't collide
+                id: format!("{}:{}", our.node, new_id()),
+                name: name.chars().take(MAX_GROUP_NAME_LENGTH).collect(),
+                members,
+                history: Vec::new(),
+            };
+            let membership = GroupInput::Membership(conversation.info());
+            let members = conversation.members.clone();
+            let conversation_id = conversation.id.clone();
+            state.groups.insert(conversation_id.clone(), conversation);
+            queue_for_members(our, state, &members, &membership);
+            state.save();
+            publish_conversation(state, &conversation_id)?;
+            poke_members(our, &members, &membership)?;
+        }
+        GroupInput::SendMessage { conversation_id, contents } => {
+            if !is_local {
+                return Ok(());
+            }
+            let Some(conversation) = state.groups.get_mut(&conversation_id) else {
+                return Ok(());
+            };
+            let message = GroupMessage {
+                id: new_id(),
+                from: our.node.clone(),
+                is_unread: false,
+                contents,
+                time_received: get_now(),
+            };
+            let fan_out = GroupInput::Message {
+                conversation_id: conversation_id.clone(),
+                message_id: message.id.clone(),
+                contents: message.contents.clone(),
+            };
+            conversation.history.push(message);
+            let members = conversation.members.clone();
+            queue_for_members(our, state, &members, &fan_out);
+            state.save();
+            publish_conversation(state, &conversation_id)?;
+            poke_members(our, &members, &fan_out)?;
+        }
+        GroupInput::Invite { conversation_id, node } => {
+            if !is_local {
+                return Ok(());
+            }
+            let Some(conversation) = state.groups.get_mut(&conversation_id) else {
+                return Ok(());
+            };
+            if conversation.members.len() >= MAX_GROUP_MEMBERS || !conversation.members.insert(node) {
+                return Ok(());
+            }
+            // everyone, including the new member, gets the updated list
+            let membership = GroupInput::Membership(conversation.info());
+            let members = conversation.members.clone();
+            queue_for_members(our, state, &members, &membership);
+            state.save();
+            publish_conversation(state, &conversation_id)?;
+            poke_members(our, &members, &membership)?;
+        }
+        GroupInput::Leave(conversation_id) => {
+            if !is_local {
+                return Ok(());
+            }
+            let Some(conversation) = state.groups.remove(&conversation_id) else {
+                return Ok(());
+            };
+            let left = GroupInput::MemberLeft(conversation_id.clone());
+            queue_for_members(our, state, &conversation.members, &left);
+            state.save();
+            update_all_consumers(state, HubOutput::GroupMessages(GroupOutput::LeftConversation(conversation_id)))?;
+            poke_members(our, &conversation.members, &left)?;
+        }
+        GroupInput::ClearUnread(conversation_id) => {
+            if !is_local {
+                return Ok(());
+            }
+            let Some(conversation) = state.groups.get_mut(&conversation_id) else {
+                return Ok(());
+            };
+            for message in &mut conversation.history {
+                message.is_unread = false;
+            }
+            state.save();
+            publish_conversation(state, &conversation_id)?;
+        }
+        GroupInput::AcceptInvite(conversation_id) => {
+            if !is_local {
+                return Ok(());
+            }
+            let Some(invite) = state.group_invites.remove(&conversation_id) else {
+                return Ok(());
+            };
+            // the members already count us in, so there is nobody to tell
+            state.groups.insert(conversation_id.clone(), GroupConversation {
+                id: invite.info.id,
+                name: invite.info.name,
+                members: invite.info.members,
+                history: Vec::new(),
+            });
+            state.save();
+            update_all_consumers(state, HubOutput::GroupMessages(GroupOutput::InviteRemoved(conversation_id.clone())))?;
+            publish_conversation(state, &conversation_id)?;
+        }
+        GroupInput::DeclineInvite(conversation_id) => {
+            if !is_local {
+                return Ok(());
+            }
+            let Some(invite) = state.group_invites.remove(&conversation_id) else {
+                return Ok(());
+            };
+            let left = GroupInput::MemberLeft(conversation_id.clone());
+            queue_for_members(our, state, &invite.info.members, &left);
+            state.save();
+            update_all_consumers(state, HubOutput::GroupMessages(GroupOutput::InviteRemoved(conversation_id)))?;
+            poke_members(our, &invite.info.members, &left)?;
+        }
+        GroupInput::Membership(info) => {
+            if is_local || !info.members.contains(&our.node) || !info.members.contains(&source.node) {
+                return Ok(());
+            }
+            if info.members.len() > MAX_GROUP_MEMBERS {
+                return Ok(());
+            }
+            let Some(conversation) = state.groups.get_mut(&info.id) else {
+                return receive_invite(state, source, info);
+            };
+            // only members may change the list, and they can only add to it.
+            // Members take themselves out with MemberLeft.
+            if !conversation.members.contains(&source.node) {
+                return Ok(());
+            }
+            let before = conversation.members.len();
+            for member in info.members {
+                if conversation.members.len() >= MAX_GROUP_MEMBERS {
+                    break;
+                }
+                conversation.members.insert(member);
+            }
+            if conversation.members.len() == before {
+                return Ok(());
+            }
+            state.save();
+            publish_conversation(state, &info.id)?;
+        }
+        GroupInput::Message { conversation_id, message_id, contents } => {
+            let Some(conversation) = state.groups.get_mut(&conversation_id) else {
+                return Ok(());
+            };
+            let is_duplicate = conversation.history.iter()
+                .any(|message| message.id == message_id && message.from == source.node);
+            if is_local || !conversation.members.contains(&source.node) || is_duplicate {
+                return Ok(());
+            }
+            conversation.history.push(GroupMessage {
+                id: message_id,
+                from: source.node.clone(),
+                is_unread: true,
+                contents,
+                time_received: get_now(),
+            });
+            state.save();
+            publish_conversation(state, &conversation_id)?;
+        }
+        GroupInput::MemberLeft(conversation_id) => {
+            let Some(conversation) = state.groups.get_mut(&conversation_id) else {
+                return Ok(());
+            };
+            if is_local || !conversation.members.remove(&source.node) {
+                return Ok(());
+            }
+            state.save();
+            publish_conversation(state, &conversation_id)?;
+        }
+        GroupInput::Ack(ack) => {
+            // keyed by member, so only the member a poke went to can clear it
+            if state.group_deliveries.remove(&(source.node.clone(), ack)).is_none() {
+                return Ok(());
+            }
+            state.save();
+        }
+    }
+    Ok(())
+}
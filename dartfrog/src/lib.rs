@@ -1,15 +1,19 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use std::str::FromStr;
 use std::time::{SystemTime, UNIX_EPOCH};
 use std::hash::{Hash, Hasher};
 
 use dartfrog_lib::*;
 mod constants;
+mod direct_messages;
+mod groups;
+use direct_messages::{Delivery, DirectMessageInput, DirectMessageOutput, DirectMessagePrivacy};
+use groups::{GroupAck, GroupConversation, GroupDelivery, GroupInput, GroupInvite, GroupOutput};
 use hyperware_process_lib::http::server::{self, send_ws_push, HttpServerRequest, WsMessageType};
-use hyperware_process_lib::{http, await_message, call_init, println, Address, Request,
+use hyperware_process_lib::{http, await_message, call_init, println, timer, Address, Request,
     get_blob,
     LazyLoadBlob,
-    set_state, get_typed_state,
+    set_state, get_state,
 };
 use serde::{Deserialize, Serialize};
 
@@ -43,6 +47,20 @@ const IS_FAKE: bool = !cfg!(feature = "prod");
 const NETWORK_HUB: &str = if IS_FAKE { "fake.os" } else { "gliderlabs.os" };
 
 
+// Hub messages that the pokes in dartfrog_lib don't cover. They arrive both
+// from the local ui and from other nodes' hubs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum HubInput {
+    DirectMessages(DirectMessageInput),
+    GroupMessages(GroupInput),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum HubOutput {
+    DirectMessages(DirectMessageOutput),
+    GroupMessages(GroupOutput),
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DartfrogState {
     pub network_hub: Option<String>,
@@ -55,6 +73,58 @@ pub struct DartfrogState {
     pub activity: PeerActivity,
     pub messages: HashMap<String, MessageStore>,
     pub rumors: Vec<String>,
+    // delivery state of direct messages we sent, by message id
+    pub deliveries: HashMap<String, Delivery>,
+    pub dm_privacy: DirectMessagePrivacy,
+    pub groups: HashMap<String, GroupConversation>,
+    // group pokes waiting on a member's ack, by member
+    pub group_deliveries: HashMap<(String, GroupAck), GroupDelivery>,
+    // groups someone added us to, by conversation id, until we accept or decline
+    pub group_invites: HashMap<String, GroupInvite>,
+    // when we last told each peer we're typing, not worth saving
+    #[serde(skip)]
+    pub typing_sent: HashMap<String, u64>,
+}
+
+// The layout saved by builds before delivery tracking, kept so message
+// history and peers survive an upgrade. bincode writes fields back to back
+// with no names, and it's a prefix of the current layout, so load has to try
+// the current layout first.
+#[derive(Debug, Clone, Deserialize)]
+struct LegacyDartfrogState {
+    network_hub: Option<String>,
+    consumers: HashMap<u32, Consumer>,
+    provider_consumers: HashMap<Address, Consumer>,
+    local_services: HashMap<String, Service>,
+    peers: HashMap<String, Peer>,
+    profile: Profile,
+    activity_setting: ActivitySetting,
+    activity: PeerActivity,
+    messages: HashMap<String, MessageStore>,
+    rumors: Vec<String>,
+}
+
+impl LegacyDartfrogState {
+    fn upgrade(self) -> DartfrogState {
+        DartfrogState {
+            network_hub: self.network_hub,
+            consumers: self.consumers,
+            provider_consumers: self.provider_consumers,
+            local_services: self.local_services,
+            peers: self.peers,
+            profile: self.profile,
+            activity_setting: self.activity_setting,
+            activity: self.activity,
+            messages: self.messages,
+            rumors: self.rumors,
+            deliveries: HashMap::new(),
+            dm_privacy: DirectMessagePrivacy::Public,
+            groups: HashMap::new(),
+            group_deliveries: HashMap::new(),
+            group_invites: HashMap::new(),
+            typing_sent: HashMap::new(),
+        }
+    }
 }
 
 impl DartfrogState {
@@ -70,6 +140,12 @@ impl DartfrogState {
             activity: PeerActivity::Offline(get_now()),
             messages: HashMap::new(),
             rumors: vec!(),
+            deliveries: HashMap::new(),
+            dm_privacy: DirectMessagePrivacy::Public,
+            groups: HashMap::new(),
+            group_deliveries: HashMap::new(),
+            group_invites: HashMap::new(),
+            typing_sent: HashMap::new(),
         }
     }
 
@@ -78,21 +154,27 @@ impl DartfrogState {
     }
 
     pub fn load(our: &Address) -> Self {
-        match get_typed_state(|bytes| bincode::deserialize::<DartfrogState>(bytes)) {
-            Some(mut state) => {
-                state.local_services = HashMap::new();
-                state
-            }
-            None => DartfrogState::new(our)
-        }
+        get_state()
+            .and_then(|bytes| DartfrogState::from_saved(&bytes))
+            .unwrap_or_else(|| DartfrogState::new(our))
+    }
+
+    fn from_saved(bytes: &[u8]) -> Option<Self> {
+        let mut state = bincode::deserialize::<DartfrogState>(bytes).ok()
+            .or_else(|| {
+                bincode::deserialize::<LegacyDartfrogState>(bytes).ok()
+                    .map(LegacyDartfrogState::upgrade)
+            })?;
+        state.local_services = HashMap::new();
+        Some(state)
     }
 }
 
 const CONSUMER_TIMEOUT : u64 = 10*60; //10 minutes
 
-fn update_consumer (
+fn update_consumer<T: Serialize> (
     websocket_id: u32,
-    update: DartfrogOutput,
+    update: T,
 ) -> anyhow::Result<()> {
 
     let blob = LazyLoadBlob {
@@ -125,9 +207,9 @@ fn update_all_of_new_peer(
     Ok(())
 }
 
-fn update_all_consumers(
+fn update_all_consumers<T: Serialize + Clone>(
     state: &DartfrogState,
-    update: DartfrogOutput,
+    update: T,
 ) -> anyhow::Result<()> {
     for consumer in state.consumers.values() {
         update_consumer(consumer.ws_channel_id, update.clone())?;
@@ -192,6 +274,10 @@ fn handle_http_server_request(
             update_consumer(channel_id, DartfrogOutput::PeerList(peers))?;
             let messages : Vec<MessageStore> = state.messages.values().cloned().collect();
             update_consumer(channel_id, DartfrogOutput::MessageStoreList(messages))?;
+            update_consumer(channel_id, direct_messages::delivery_list(state))?;
+            update_consumer(channel_id, HubOutput::DirectMessages(DirectMessageOutput::Privacy(state.dm_privacy)))?;
+            update_consumer(channel_id, groups::conversation_list(state))?;
+            update_consumer(channel_id, groups::invite_list(state))?;
             let local_user = DartfrogOutput::LocalUser(state.profile.clone(), state.activity.clone(), state.activity_setting.clone());
             update_consumer(channel_id, local_user)?;
             state.save(); // Save after adding a new consumer
@@ -220,6 +306,10 @@ fn handle_http_server_request(
                 return Ok(());
             };
 
+            if let Ok(hub_input) = serde_json::from_slice::<HubInput>(&blob.bytes) {
+                return handle_hub_input(our, state, source, hub_input);
+            }
+
             match serde_json::from_slice(&blob.bytes)? {
                 DartfrogInput::RequestVersion => {
                     let network_hub_address = get_server_address(NETWORK_HUB);
@@ -375,24 +465,31 @@ fn handle_http_server_request(
                             };
                             
                             message_store.history.push(new_message.clone());
+                            // pending until the peer acknowledges it
+                            state.deliveries.insert(new_message.id.clone(), Delivery::new(node.clone(), get_now()));
                             
                             // Update local consumers
                             update_all_consumers(state, DartfrogOutput::MessageStoreList(state.messages.values().cloned().collect()))?;
+                            let status = state.deliveries[&new_message.id].status(&new_message.id);
+                            update_all_consumers(state, HubOutput::DirectMessages(DirectMessageOutput::Delivery(status)))?;
                             
                             // Send message to remote peer
-                            let remote_poke = RemoteDirectMessagePoke::SendMessage(new_message.id, new_message.contents);
-                            let address = get_server_address(&node);
-                            poke(&address, DartfrogInput::RemoteDirectMessages(remote_poke))?;
+                            direct_messages::send_to_peer(&node, &new_message)?;
                             
                             state.save(); // Save after sending a message
                         },
                         LocalDirectMessagePoke::ClearUnreadMessageStore(node) => {
                             if let Some(message_store) = state.messages.get_mut(&node) {
+                                let mut read_ids = Vec::new();
                                 for message in &mut message_store.history {
+                                    if message.is_unread {
+                                        read_ids.push(message.id.clone());
+                                    }
                                     message.is_unread = false;
                                 }
                                 update_all_consumers(state, DartfrogOutput::MessageStoreList(state.messages.values().cloned().collect()))?;
                                 state.save(); // Save after clearing unread messages
+                                direct_messages::send_read_receipts(state, &node, read_ids)?;
                             }
                         },
                     }
@@ -628,6 +725,13 @@ fn handle_dartfrog_input(
                     let message_store = state.messages.entry(source.node.clone())
                         .or_insert_with(|| MessageStore::new(source.node.clone()));
                     
+                    // retries can deliver the same message twice, so only the ack is repeated
+                    if message_store.history.iter().any(|message| message.id == id && message.from == source.node) {
+                        direct_messages::acknowledge(&source.node, id)?;
+                        return Ok(());
+                    }
+                    let ack_id = id.clone();
+                    
                     let new_message = DirectMessage {
                         id: id,
                         from: source.node.clone(),
@@ -642,6 +746,7 @@ fn handle_dartfrog_input(
                     update_all_consumers(state, DartfrogOutput::MessageStore(message_store_clone))?;
                     
                     state.save(); // Save after adding a new message
+                    direct_messages::acknowledge(&source.node, ack_id)?;
                 }
             }
         }
@@ -652,11 +757,30 @@ fn handle_dartfrog_input(
     Ok(())
 }
 
+fn handle_hub_input(
+    our: &Address,
+    state: &mut DartfrogState,
+    source: &Address,
+    hub_input: HubInput,
+) -> anyhow::Result<()> {
+    match hub_input {
+        HubInput::DirectMessages(dm_input) => direct_messages::handle_input(our, state, source, dm_input),
+        HubInput::GroupMessages(group_input) => groups::handle_input(our, state, source, group_input),
+    }
+}
+
 fn handle_message(our: &Address, state: &mut DartfrogState) -> anyhow::Result<()> {
     let message = await_message()?;
 
     let body = message.body();
     let source = message.source();
+    // the timer answers set_timer with a Response
+    if source.node == our.node && source.process == "timer:distro:sys" {
+        timer::set_timer(TIMER_INTERVAL * 1000, None);
+        let result = direct_messages::retry_due(state);
+        groups::retry_due(state)?;
+        return result;
+    }
     if !message.is_request() {
         return Err(anyhow::anyhow!("unexpected Response: {:?}", message));
     }
@@ -664,6 +788,9 @@ fn handle_message(our: &Address, state: &mut DartfrogState) -> anyhow::Result<()
         && message.source().process == "http-server:distro:sys" {
         handle_http_server_request(our, state, source, body)
     } else {
+        if let Ok(hub_input) = serde_json::from_slice::<HubInput>(&body) {
+            handle_hub_input(our, state, source, hub_input)?;
+        }
         if let Ok(app_message) = serde_json::from_slice::<ProviderOutput>(&body) {
             handle_provider_output(our, state, source, app_message)?;
         }
@@ -684,10 +811,17 @@ fn handle_message(our: &Address, state: &mut DartfrogState) -> anyhow::Result<()
                 }
             }
         }
+        if source.node != our.node {
+            direct_messages::resend_pending_to(state, &source.node)?;
+            groups::resend_pending_to(state, &source.node)?;
+        }
         Ok(())
     }
 }
 
+// Delivery retries are due whether or not anything else is happening
+const TIMER_INTERVAL: u64 = 30; // seconds
+
 call_init!(init);
 fn init(our: Address) {
     println!("initializing");
@@ -736,10 +870,100 @@ fn init(our: Address) {
     poke(&network_hub_address, DartfrogInput::RemoteRequestAllPeerNodes).unwrap();
 
     state.save();
+    timer::set_timer(TIMER_INTERVAL * 1000, None);
 
     loop {
         if let Err(e) = handle_message(&our, &mut state) {
             println!("handle_message error: {:?}", e);
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    fn our() -> Address {
+        Address::new("host.os", ("dartfrog", "dartfrog", "gliderlabs.os"))
+    }
+
+    // A save from before delivery tracking. bincode writes a struct the same
+    // way as a tuple of its fields, in order.
+    fn baseline_save() -> Vec<u8> {
+        let mut peers = HashMap::new();
+        peers.insert("a.os".to_string(), Peer::new("a.os".to_string()));
+        let mut messages = HashMap::new();
+        messages.insert("a.os".to_string(), MessageStore::new("a.os".to_string()));
+        bincode::serialize(&(
+            Some("gliderlabs.os".to_string()),
+            HashMap::<u32, Consumer>::new(),
+            HashMap::<Address, Consumer>::new(),
+            HashMap::<String, Service>::new(),
+            peers,
+            Profile::new("host.os".to_string()),
+            ActivitySetting::Public,
+            PeerActivity::Offline(5),
+            messages,
+            vec!["hello".to_string()],
+        )).unwrap()
+    }
+
+    // Maps serialize in hash order, so the parts json can hold are compared as values
+    fn assert_same(loaded: &DartfrogState, state: &DartfrogState) {
+        assert_eq!(loaded.network_hub, state.network_hub);
+        assert_eq!(loaded.rumors, state.rumors);
+        assert_eq!(loaded.dm_privacy, state.dm_privacy);
+        assert_eq!(loaded.peers.keys().collect::<HashSet<_>>(), state.peers.keys().collect::<HashSet<_>>());
+        assert_eq!(serde_json::to_value(&loaded.messages).unwrap(), serde_json::to_value(&state.messages).unwrap());
+        assert_eq!(serde_json::to_value(&loaded.profile).unwrap(), serde_json::to_value(&state.profile).unwrap());
+        assert_eq!(serde_json::to_value(&loaded.deliveries).unwrap(), serde_json::to_value(&state.deliveries).unwrap());
+        assert_eq!(serde_json::to_value(&loaded.groups).unwrap(), serde_json::to_value(&state.groups).unwrap());
+        assert_eq!(loaded.group_deliveries.len(), state.group_deliveries.len());
+        assert_eq!(serde_json::to_value(&loaded.group_invites).unwrap(), serde_json::to_value(&state.group_invites).unwrap());
+    }
+
+    // The same bytes that save writes, loaded back through load's path
+    fn reload(state: &DartfrogState) -> DartfrogState {
+        DartfrogState::from_saved(&bincode::serialize(state).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn baseline_save_loads_and_upgrades() {
+        let state = DartfrogState::from_saved(&baseline_save()).unwrap();
+        assert_eq!(state.network_hub.as_deref(), Some("gliderlabs.os"));
+        assert!(state.peers.contains_key("a.os"));
+        assert!(state.messages.contains_key("a.os"));
+        assert_eq!(state.rumors, vec!["hello".to_string()]);
+        assert_eq!(state.dm_privacy, DirectMessagePrivacy::Public);
+        assert!(state.deliveries.is_empty());
+        assert!(state.groups.is_empty());
+        let loaded = reload(&state);
+        assert_same(&loaded, &state);
+    }
+
+    #[test]
+    fn current_save_reloads() {
+        let mut state = DartfrogState::new(&our());
+        state.rumors.push("hello".to_string());
+        state.dm_privacy = DirectMessagePrivacy::Private;
+        state.messages.insert("a.os".to_string(), MessageStore::new("a.os".to_string()));
+        let info = groups::GroupInfo {
+            id: "a.os:1".to_string(),
+            name: "frogs".to_string(),
+            members: ["a.os".to_string(), "host.os".to_string()].into(),
+        };
+        state.group_invites.insert(info.id.clone(), GroupInvite {
+            info,
+            from: "a.os".to_string(),
+            received_at: 5,
+        });
+        let loaded = reload(&state);
+        assert_same(&loaded, &state);
+    }
+
+    #[test]
+    fn unreadable_save_is_refused() {
+        assert!(DartfrogState::from_saved(&[1, 2, 3]).is_none());
+    }
+}
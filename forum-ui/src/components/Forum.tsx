@@ -13,6 +13,7 @@ import { useServiceStore } from '@dartfrog/puddle';
 
 const Forum: React.FC = () => {
   const { peerMap, serviceMetadata, } = useServiceStore();
+  const { notice, clearNotice } = useForumStore();
 
   return (
     <div
@@ -27,6 +28,22 @@ const Forum: React.FC = () => {
         boxSizing: "border-box",
       }}
     >
+      {notice && (
+        <div
+          style={{
+            display: "flex",
+            flexDirection: "row",
+            gap: "0.5rem",
+            alignItems: "center",
+            fontSize: "0.8rem",
+            color: "#ff6b6b",
+            marginBottom: "0.5rem",
+          }}
+        >
+          <span style={{ flexGrow: 1 }}>{notice}</span>
+          <button onClick={clearNotice}>dismiss</button>
+        </div>
+      )}
       <Routes>
           <Route path="/post/:postId" element={
             <>
@@ -10,7 +10,7 @@ const DEFAULT_DESCRIPTION = 'description';
 
 const ForumAdmin: React.FC = () => {
   const { api, serviceId, serviceMetadata, editService, fullServiceMetadata } = useServiceStore();
-  const { banUser, unbanUser, bannedUsers } = useForumStore();
+  const { banUser, unbanUser, bannedUsers, mutedUsers } = useForumStore();
   const [newTitle, setNewTitle] = useState(DEFAULT_TITLE);
   const [newDescription, setNewDescription] = useState(DEFAULT_DESCRIPTION);
   const [userToBan, setUserToBan] = useState('');
@@ -199,6 +199,39 @@ const ForumAdmin: React.FC = () => {
               </div>
             ))}
           </div>
+          <div>muted users</div>
+          <div
+            style={{
+              maxHeight: "200px",
+              overflowY: "auto",
+              borderRadius: "4px",
+            }}
+          >
+            {mutedUsers.map((user) => (
+              <div
+                key={user}
+                style={{
+                  display: "flex",
+                  alignItems: "center",
+                  padding: "0.5rem",
+                  borderBottom: "1px solid #333",
+                  gap:"1rem",
+                }}
+              >
+                <button
+                  onClick={() => handleUnbanUser(user)}
+                  style={{
+                    padding: "0.25rem 0.5rem",
+                    borderRadius: "4px",
+                    cursor: "pointer",
+                  }}
+                >
+                  unmute
+                </button>
+                <span>{user}</span>
+              </div>
+            ))}
+          </div>
         </div>
       </div>
       {/* <div>
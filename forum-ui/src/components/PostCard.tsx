@@ -113,7 +113,15 @@ const PostCard: React.FC<PostCardProps> = ({ post_id, showFullContents = false,
           fontSize: "0.8rem",
         }}
       >
-        {!post.author ? (
+        {post.deleted ? (
+          <span
+            style={{
+              cursor:"default",
+            }}
+          >
+            deleted
+          </span>
+        ) : !post.author ? (
           <span
             style={{
               cursor:"default",
@@ -385,7 +393,7 @@ const PostCard: React.FC<PostCardProps> = ({ post_id, showFullContents = false,
             }
           </>
         )}
-        {isAdmin && !post.author && (
+        {isAdmin && !post.author && !post.deleted && (
           <div
             onClick={(e) => {
               e.stopPropagation();
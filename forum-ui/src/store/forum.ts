@@ -16,11 +16,17 @@ export interface ForumPost {
   is_sticky: boolean
   is_anon: boolean
   thread_id?: number
+  // deleted by its author, kept for the replies under it
+  deleted?: boolean
 }
 
 export interface ForumStore {
   posts: ForumPost[]
   bannedUsers: string[]
+  mutedUsers: string[]
+  // why the host turned down our last post, edit or report, or that it's held for review
+  notice: string | null
+  clearNotice: () => void
   createPost: (api: ServiceApi, post: Omit<ForumPost, 'id' | 'author' | 'upvotes' | 'downvotes' | 'comments' | 'created_at' | 'is_sticky'>) => void
   createStickyPost: (api: ServiceApi, post: Omit<ForumPost, 'id' | 'author' | 'upvotes' | 'downvotes' | 'comments' | 'created_at' | 'is_sticky' | 'thread_id'>) => void
   vote: (api: ServiceApi, postId: number, isUpvote: boolean) => void
@@ -41,12 +47,20 @@ export type ForumUpdate =
   | { NewPost: ForumPost }
   | { UpdatedPost: ForumPost }
   | { BannedUsers: string[] }
+  | { MutedUsers: string[] }
   | { DeletedPost: number }
   | { PostAuthor: { post_id: number, author: string } }
+  | { PostRejected: { reason: string } }
+  | { ReportRejected: { post_id: number, reason: string } }
+  | { PostHeld: { post_id: number } }
 
 const useForumStore = create<ForumStore>((set, get) => ({
   posts: [],
   bannedUsers: [],
+  mutedUsers: [],
+  notice: null,
+
+  clearNotice: () => set({ notice: null }),
 
   createPost: (api, post) => {
     const req = {
@@ -215,6 +229,8 @@ const useForumStore = create<ForumStore>((set, get) => ({
         return { posts: updatedPosts };
       } else if ('BannedUsers' in update) {
         return { bannedUsers: update.BannedUsers }
+      } else if ('MutedUsers' in update) {
+        return { mutedUsers: update.MutedUsers }
       } else if ('DeletedPost' in update) {
         return { posts: state.posts.filter(post => post.id !== update.DeletedPost) }
       } else if ('PostAuthor' in update) {
@@ -225,6 +241,12 @@ const useForumStore = create<ForumStore>((set, get) => ({
               : post
           )
         }
+      } else if ('PostRejected' in update) {
+        return { notice: update.PostRejected.reason }
+      } else if ('ReportRejected' in update) {
+        return { notice: `report not sent: ${update.ReportRejected.reason}` }
+      } else if ('PostHeld' in update) {
+        return { notice: "your post is waiting for a moderator to approve it" }
       }
       return state
     })
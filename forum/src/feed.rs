@@ -0,0 +1,96 @@
+use crate::PublicForumPost;
+
+const MAX_TITLE_LENGTH: usize = 80;
+
+pub struct FeedInfo {
+    pub id: String,
+    pub title: String,
+    pub author: String,
+    pub self_path: String,
+    pub alternate_path: String,
+}
+
+// Builds an Atom document from public posts, so anonymous authors stay hidden
+pub fn atom_feed(info: &FeedInfo, posts: &[PublicForumPost]) -> String {
+    let updated = posts.iter()
+        .map(|post| post.edited_at.unwrap_or(post.created_at))
+        .max()
+        .unwrap_or(0);
+
+    let mut xml = String::new();
+    xml.push_str(r#"<?xml version="1.0" encoding="utf-8"?>"#);
+    xml.push('\n');
+    xml.push_str(r#"<feed xmlns="http://www.w3.org/2005/Atom">"#);
+    xml.push('\n');
+    xml.push_str(&format!("  <id>{}</id>\n", escape(&info.id)));
+    xml.push_str(&format!("  <title>{}</title>\n", escape(&info.title)));
+    xml.push_str(&format!("  <updated>{}</updated>\n", rfc3339(updated)));
+    xml.push_str(&format!("  <author><name>{}</name></author>\n", escape(&info.author)));
+    xml.push_str(&format!("  <link rel=\"self\" href=\"{}\"/>\n", escape(&info.self_path)));
+    xml.push_str(&format!("  <link rel=\"alternate\" href=\"{}\"/>\n", escape(&info.alternate_path)));
+    for post in posts {
+        xml.push_str("  <entry>\n");
+        xml.push_str(&format!("    <id>{}:{}</id>\n", escape(&info.id), post.id));
+        xml.push_str(&format!("    <title>{}</title>\n", escape(&entry_title(post))));
+        xml.push_str(&format!("    <published>{}</published>\n", rfc3339(post.created_at)));
+        xml.push_str(&format!("    <updated>{}</updated>\n", rfc3339(post.edited_at.unwrap_or(post.created_at))));
+        if let Some(author) = post.author.as_ref().or(post.pseudonym.as_ref()) {
+            xml.push_str(&format!("    <author><name>{}</name></author>\n", escape(author)));
+        }
+        if let Some(link) = &post.link {
+            xml.push_str(&format!("    <link href=\"{}\"/>\n", escape(link)));
+        }
+        if let Some(category) = &post.category {
+            xml.push_str(&format!("    <category term=\"{}\"/>\n", escape(category)));
+        }
+        xml.push_str(&format!("    <content type=\"text\">{}</content>\n", escape(&post.text_contents)));
+        xml.push_str("  </entry>\n");
+    }
+    xml.push_str("</feed>\n");
+    xml
+}
+
+fn entry_title(post: &PublicForumPost) -> String {
+    let first_line = post.text_contents.lines().next().unwrap_or("").trim();
+    let title = if !first_line.is_empty() {
+        first_line
+    } else if let Some(link) = &post.link {
+        link.as_str()
+    } else {
+        "untitled post"
+    };
+    if title.chars().count() > MAX_TITLE_LENGTH {
+        let truncated: String = title.chars().take(MAX_TITLE_LENGTH).collect();
+        format!("{}…", truncated)
+    } else {
+        title.to_string()
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+// Unix seconds to an RFC 3339 UTC timestamp
+fn rfc3339(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+    // civil-from-days, Howard Hinnant's algorithm
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, rem / 3600, (rem % 3600) / 60, rem % 60
+    )
+}
@@ -1,8 +1,27 @@
 use std::collections::{HashMap, HashSet};
 
 use dartfrog_lib::*;
-use hyperware_process_lib::{call_init, http::server, Address};
+use hyperware_process_lib::{call_init, http::server, Address, LazyLoadBlob};
 use serde::{Serialize, Deserialize};
+use bans::{new_ban, BanEntry, BanKind, BanList};
+use feed::FeedInfo;
+use poll::{NewPoll, Poll, PublicPoll};
+use preview::LinkPreview;
+use search::SearchIndex;
+use spam::{RateLimiter, SpamSettings, Verdict};
+use timers::{for_each_service, handle_timers};
+
+#[path = "../../shared/bans.rs"]
+mod bans;
+mod feed;
+mod poll;
+mod preview;
+#[path = "../../shared/pseudonym.rs"]
+mod pseudonym;
+mod search;
+mod spam;
+#[path = "../../shared/timers.rs"]
+mod timers;
 
 wit_bindgen::generate!({
     path: "target/wit",
@@ -32,12 +51,14 @@ pub enum AppRequest {
 #[derive(Debug, Clone)]
 pub struct AppState {
     pub provider: AppProviderState,
+    pub next_timer: u64,
 }
 
 impl AppState {
     pub fn new(our: &Address) -> Self {
         AppState {
             provider: AppProviderState::new(our),
+            next_timer: 0,
         }
     }
 }
@@ -51,7 +72,16 @@ impl AppServiceState for AppService {
     }
 
     fn init(&mut self, our: &Address, service: &Service) -> anyhow::Result<()> {
-        default_load_service::<Self>(our, &service.id.to_string(), self)
+        default_load_service::<Self>(our, &service.id.to_string(), self)?;
+        self.forum.upgrade(our);
+        // filters that stopped compiling were already skipped before the restart
+        let _ = self.forum.spam_settings.compile();
+        self.forum.rebuild_search_index();
+        self.forum.schedule_poll_close();
+        self.forum.sync_feeds(our, service)?;
+        // upgrades and anything new() made up for missing fields, like the
+        // pseudonym secret, have to be kept or they change every restart
+        self.save(our, service)
     }
 
     fn save(&mut self, our: &Address, service: &Service) -> anyhow::Result<()> {
@@ -80,6 +110,26 @@ impl AppServiceState for AppService {
     }
 }
 
+impl AppService {
+    // Polls close on their own, so their announcements can't wait for a request
+    fn handle_timer(&mut self, our: &Address, service: &Service) -> anyhow::Result<()> {
+        // the service's access can change without a request
+        self.forum.sync_feeds(our, service)?;
+        if self.forum.announce_closed_polls(get_now(), our, service)? {
+            self.save(our, service)?;
+        }
+        self.fetch_link_previews(our, service)
+    }
+
+    // Runs from the process loop once a request has been handled and saved
+    fn fetch_link_previews(&mut self, our: &Address, service: &Service) -> anyhow::Result<()> {
+        if self.forum.fetch_link_previews(our, service)? {
+            self.save(our, service)?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ForumPost {
     id: u64,
@@ -95,6 +145,36 @@ pub struct ForumPost {
     is_sticky: bool,
     is_anon: bool,
     thread_id: Option<u64>,
+    // posts saved before nested replies only have thread_id, see ForumServiceState::upgrade
+    #[serde(default)]
+    parent_id: Option<u64>,
+    #[serde(default)]
+    depth: u32,
+    #[serde(default)]
+    edited_at: Option<u64>,
+    #[serde(default)]
+    edit_history: Vec<PostRevision>,
+    #[serde(default)]
+    category: Option<String>,
+    #[serde(default)]
+    poll: Option<Poll>,
+    #[serde(default)]
+    link_preview: Option<LinkPreview>,
+    // set on anonymous posts so readers can follow one author through a thread
+    #[serde(default)]
+    pseudonym: Option<String>,
+    // deleted by its author but kept in place for the replies under it
+    #[serde(default)]
+    deleted: bool,
+}
+
+// A previous version of a post, kept whenever the author edits it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PostRevision {
+    text_contents: String,
+    link: Option<String>,
+    image_url: Option<String>,
+    replaced_at: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -110,22 +190,76 @@ pub struct PublicForumPost {
     created_at: u64,
     is_sticky: bool,
     thread_id: Option<u64>,
+    parent_id: Option<u64>,
+    depth: u32,
+    edited_at: Option<u64>,
+    category: Option<String>,
+    poll: Option<PublicPoll>,
+    link_preview: Option<LinkPreview>,
+    pseudonym: Option<String>,
+    deleted: bool,
 }
 
 impl ForumPost {
+    fn searchable_text(&self) -> String {
+        let mut text = self.text_contents.clone();
+        if let Some(link) = &self.link {
+            text.push(' ');
+            text.push_str(link);
+        }
+        if let Some(poll) = &self.poll {
+            text.push(' ');
+            text.push_str(&poll.searchable_text());
+        }
+        text
+    }
+
+    fn score(&self) -> i64 {
+        self.upvotes as i64 - self.downvotes as i64
+    }
+
+    fn hot_rank(&self) -> f64 {
+        let score = self.score();
+        let order = (score.abs().max(1) as f64).log10();
+        let sign = score.signum() as f64;
+        sign * order + self.created_at as f64 / HOT_DECAY_SECONDS
+    }
+
+    // Many votes split close to evenly rank highest
+    fn controversy(&self) -> f64 {
+        if self.upvotes == 0 || self.downvotes == 0 {
+            return 0.0;
+        }
+        let magnitude = (self.upvotes + self.downvotes) as f64;
+        let balance = if self.upvotes > self.downvotes {
+            self.downvotes as f64 / self.upvotes as f64
+        } else {
+            self.upvotes as f64 / self.downvotes as f64
+        };
+        magnitude.powf(balance)
+    }
+
     pub fn to_public(&self, include_author: bool) -> PublicForumPost {
         PublicForumPost {
             id: self.id,
             text_contents: self.text_contents.clone(),
             link: self.link.clone(),
             image_url: self.image_url.clone(),
-            author: if self.is_anon { None } else if include_author { Some(self.author.clone()) } else { None },
+            author: if self.is_anon || self.deleted { None } else if include_author { Some(self.author.clone()) } else { None },
             upvotes: self.upvotes,
             downvotes: self.downvotes,
             comments: self.comments.clone(),
             created_at: self.created_at,
             is_sticky: self.is_sticky,
             thread_id: self.thread_id,
+            parent_id: self.parent_id,
+            depth: self.depth,
+            edited_at: self.edited_at,
+            category: self.category.clone(),
+            poll: self.poll.as_ref().map(|poll| poll.to_public(get_now())),
+            link_preview: self.link_preview.clone(),
+            pseudonym: self.pseudonym.clone(),
+            deleted: self.deleted,
         }
     }
 }
@@ -136,11 +270,62 @@ pub enum ForumUpdate {
     NewPost(PublicForumPost),
     UpdatedPost(PublicForumPost),
     BannedUsers(Vec<String>),
+    MutedUsers(Vec<String>),
     DeletedPost(u64),
     PostAuthor {
         post_id: u64,
         author: String,
     },
+    PostsPage {
+        posts: Vec<PublicForumPost>,
+        next_cursor: Option<Cursor>,
+    },
+    CommentsPage {
+        post_id: u64,
+        comments: Vec<PublicForumPost>,
+        next_cursor: Option<Cursor>,
+    },
+    PostHistory {
+        post_id: u64,
+        history: Vec<PostRevision>,
+    },
+    Categories {
+        categories: Vec<ForumCategory>,
+        require_category: bool,
+    },
+    SearchResults {
+        query: String,
+        posts: Vec<PublicForumPost>,
+        next_cursor: Option<Cursor>,
+    },
+    ModerationQueue(Vec<QueuedReport>),
+    NewReport(QueuedReport),
+    ResolvedReport(u64),
+    AuditLog(Vec<AuditLogEntry>),
+    Moderators(Vec<Moderator>),
+    Bans(Vec<BanEntry>),
+    BanLookup {
+        user: String,
+        ban: Option<BanEntry>,
+    },
+    PostRejected {
+        reason: String,
+    },
+    ReportRejected {
+        post_id: u64,
+        reason: String,
+    },
+    PostHeld {
+        post_id: u64,
+    },
+    HeldPosts(Vec<PublicForumPost>),
+    // a single post newly held for review
+    HeldPost(PublicForumPost),
+    SpamSettings(SpamSettings),
+    FeedUrl {
+        thread_id: Option<u64>,
+        url: String,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -151,6 +336,22 @@ pub enum ForumRequest {
         image_url: Option<String>,
         is_anon: bool,
         thread_id: Option<u64>,
+        parent_id: Option<u64>,
+        category: Option<String>,
+        poll: Option<NewPoll>,
+    },
+    VotePoll {
+        post_id: u64,
+        choices: Vec<usize>,
+    },
+    EditPost {
+        post_id: u64,
+        text_contents: String,
+        link: Option<String>,
+        image_url: Option<String>,
+    },
+    GetPostHistory {
+        post_id: u64,
     },
     Vote {
         post_id: u64,
@@ -161,10 +362,24 @@ pub enum ForumRequest {
     },
     BanUser {
         user: String,
+        reason: Option<String>,
+        duration: Option<u64>, // seconds, permanent if None
+    },
+    MuteUser {
+        user: String,
+        reason: Option<String>,
+        duration: Option<u64>,
     },
     UnbanUser {
         user: String,
     },
+    GetBan {
+        user: String,
+    },
+    ExportBans,
+    ImportBans {
+        bans: Vec<BanEntry>,
+    },
     DeletePost {
         post_id: u64,
     },
@@ -173,6 +388,7 @@ pub enum ForumRequest {
         link: Option<String>,
         image_url: Option<String>,
         is_anon: bool,
+        category: Option<String>,
     },
     ToggleSticky {
         post_id: u64,
@@ -180,13 +396,247 @@ pub enum ForumRequest {
     GetPostAuthor {
         post_id: u64,
     },
+    // cursors are the next_cursor of the page before
+    ListPosts {
+        cursor: Option<Cursor>,
+        limit: Option<usize>,
+        sort: Option<ForumSort>,
+        category: Option<String>,
+    },
+    ListComments {
+        post_id: u64,
+        cursor: Option<Cursor>,
+        limit: Option<usize>,
+        sort: Option<ForumSort>,
+    },
+    AddCategory {
+        name: String,
+        description: Option<String>,
+        color: Option<String>,
+    },
+    RemoveCategory {
+        name: String,
+    },
+    SetRequireCategory {
+        require_category: bool,
+    },
+    SetPostCategory {
+        post_id: u64,
+        category: Option<String>,
+    },
+    Search {
+        query: String,
+        cursor: Option<Cursor>,
+        limit: Option<usize>,
+    },
+    ReportPost {
+        post_id: u64,
+        reason: String,
+    },
+    GetModerationQueue,
+    ResolveReport {
+        report_id: u64,
+        resolution: ReportResolution,
+    },
+    GetAuditLog,
+    SetModerator {
+        node: String,
+        permissions: Vec<ModeratorPermission>,
+    },
+    RemoveModerator {
+        node: String,
+    },
+    SetSpamSettings(SpamSettings),
+    GetSpamSettings,
+    GetHeldPosts,
+    ApproveHeldPost {
+        post_id: u64,
+    },
+    RejectHeldPost {
+        post_id: u64,
+    },
+    GetThreadFeed {
+        post_id: u64,
+    },
+}
+
+const FEED_LENGTH: usize = 50;
+const FEED_MIME: &str = "application/atom+xml; charset=utf-8";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ModeratorPermission {
+    BanUsers,
+    DeletePosts,
+    // pin and unpin existing posts
+    StickyPosts,
+    // post new threads that start out pinned
+    CreateStickyPosts,
+    RevealAuthors,
+    HandleReports,
+    ViewAuditLog,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Moderator {
+    node: String,
+    permissions: Vec<ModeratorPermission>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PostReport {
+    id: u64,
+    post_id: u64,
+    reporter: String,
+    reason: String,
+    created_at: u64,
+}
+
+// A report as moderators see it, alongside the post it is about
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueuedReport {
+    report: PostReport,
+    post: PublicForumPost,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ReportResolution {
+    Dismiss,
+    RemovePost,
+    BanAuthor,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ModerationAction {
+    DismissReport { report_id: u64, post_id: u64 },
+    RemovePost { post_id: u64 },
+    BanUser { user: String, kind: BanKind, reason: Option<String>, expires_at: Option<u64> },
+    UnbanUser { user: String },
+    ImportBans { count: usize },
+    ToggleSticky { post_id: u64, is_sticky: bool },
+    RevealAuthor { post_id: u64 },
+    SetModerator { node: String, permissions: Vec<ModeratorPermission> },
+    RemoveModerator { node: String },
+    UpdateSpamSettings,
+    ApproveHeldPost { post_id: u64 },
+    RejectHeldPost { post_id: u64 },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditLogEntry {
+    actor: String,
+    action: ModerationAction,
+    time: u64,
+}
+
+const MAX_REPORT_REASON_LENGTH: usize = 500;
+// open reports kept at once, new ones are turned away until moderators catch up
+const MAX_OPEN_REPORTS: usize = 1000;
+const MAX_AUDIT_LOG_ENTRIES: usize = 2000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ForumCategory {
+    name: String,
+    description: Option<String>,
+    color: Option<String>,
+}
+
+const MAX_CATEGORIES: usize = 32;
+const MAX_CATEGORY_NAME_LENGTH: usize = 32;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum ForumSort {
+    Hot,
+    Top(TopWindow),
+    New,
+    Controversial,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum TopWindow {
+    Day,
+    Week,
+    All,
+}
+
+const DAY_SECONDS: u64 = 24 * 60 * 60;
+// how many seconds of age cancel out a 10x difference in score for hot
+const HOT_DECAY_SECONDS: f64 = 45000.0;
+
+impl TopWindow {
+    fn cutoff(&self, now: u64) -> u64 {
+        match self {
+            TopWindow::Day => now.saturating_sub(DAY_SECONDS),
+            TopWindow::Week => now.saturating_sub(7 * DAY_SECONDS),
+            TopWindow::All => 0,
+        }
+    }
+}
+
+const DEFAULT_PAGE_SIZE: usize = 30;
+const MAX_PAGE_SIZE: usize = 100;
+const PREVIEW_RETRY_AFTER: u64 = 24 * 60 * 60; // seconds
+const PREVIEW_REQUEUE_INTERVAL: u64 = 10 * 60; // seconds
+const PREVIEW_FETCH_EXPIRY: u64 = 2 * 60; // seconds
+const PREVIEW_FETCH_WINDOW: u64 = 10 * 60; // seconds
+const MAX_PREVIEW_FETCHES_PER_AUTHOR: usize = 5;
+const MAX_PREVIEW_FETCHES: usize = 60;
+// older revisions of an edited post are dropped
+const MAX_EDIT_HISTORY: usize = 20;
+const MAX_COMMENT_DEPTH: u32 = 16;
+
+// Fields missing from older saves take their value from new()
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ForumServiceState {
     pub posts: HashMap<u64, ForumPost>,
     pub next_post_id: u64,
-    pub banned_users: HashSet<String>,
+    pub bans: BanList,
+    pub categories: Vec<ForumCategory>,
+    pub require_category: bool,
+    #[serde(skip)]
+    pub search_index: SearchIndex,
+    pub reports: Vec<PostReport>,
+    pub next_report_id: u64,
+    pub audit_log: Vec<AuditLogEntry>,
+    pub moderators: HashMap<String, HashSet<ModeratorPermission>>,
+    pub spam_settings: SpamSettings,
+    pub first_seen: HashMap<String, u64>,
+    // posts caught by a hold filter, invisible until a moderator approves them
+    pub held_posts: HashMap<u64, ForumPost>,
+    #[serde(skip)]
+    pub rate_limiter: RateLimiter,
+    // threads someone asked for a feed of, kept bound and up to date
+    pub thread_feeds: HashSet<u64>,
+    // only used to derive anonymous posters' pseudonyms, never sent out
+    pub pseudonym_secret: [u8; 32],
+    // posts whose link still needs a preview, fetched after the request that added them
+    pub pending_previews: Vec<u64>,
+    // links whose preview couldn't be fetched, and when that last happened
+    pub preview_failures: HashMap<String, u64>,
+    // posts whose preview an edit queued again, and when
+    #[serde(skip)]
+    preview_requeues: HashMap<u64, u64>,
+    // links handed to linkpreview and not yet collected, and when
+    #[serde(skip)]
+    previews_in_flight: HashMap<String, u64>,
+    // when each recent fetch was started, and for whose post
+    #[serde(skip)]
+    preview_fetches: Vec<(u64, String)>,
+    // whether feeds are bound right now, None until the first sync after loading
+    #[serde(skip)]
+    feeds_bound: Option<bool>,
+    // earliest close among polls not yet announced as closed
+    #[serde(skip)]
+    next_poll_close: Option<u64>,
+    // the plain ban list saved before timed bans, moved into bans on load
+    #[serde(rename = "banned_users", skip_serializing)]
+    legacy_banned_users: HashSet<String>,
+}
+
+impl Default for ForumServiceState {
+    fn default() -> Self {
+        ForumServiceState::new()
+    }
 }
 
 impl ForumServiceState {
@@ -194,64 +644,171 @@ impl ForumServiceState {
         ForumServiceState {
             posts: HashMap::new(),
             next_post_id: 1,
-            banned_users: HashSet::new(),
+            bans: BanList::default(),
+            categories: Vec::new(),
+            require_category: false,
+            search_index: SearchIndex::default(),
+            reports: Vec::new(),
+            next_report_id: 1,
+            audit_log: Vec::new(),
+            moderators: HashMap::new(),
+            spam_settings: SpamSettings::default(),
+            first_seen: HashMap::new(),
+            held_posts: HashMap::new(),
+            rate_limiter: RateLimiter::default(),
+            thread_feeds: HashSet::new(),
+            pseudonym_secret: rand::random(),
+            pending_previews: Vec::new(),
+            preview_failures: HashMap::new(),
+            preview_requeues: HashMap::new(),
+            previews_in_flight: HashMap::new(),
+            preview_fetches: Vec::new(),
+            feeds_bound: None,
+            next_poll_close: None,
+            legacy_banned_users: HashSet::new(),
         }
     }
 
-    fn handle_subscribe(&mut self, subscriber_node: String, our: &Address, service: &Service) -> anyhow::Result<()> {
-        let mut public_posts: Vec<PublicForumPost> = self.posts.values()
-            .map(|post| post.to_public(true))
-            .collect();
-        
-        // Sort posts: sticky posts first, then by created_at in descending order
-        public_posts.sort_by(|a, b| {
-            match (a.is_sticky, b.is_sticky) {
-                (true, false) => std::cmp::Ordering::Less,
-                (false, true) => std::cmp::Ordering::Greater,
-                _ => b.created_at.cmp(&a.created_at),
+    // Brings state saved by older versions up to date
+    fn upgrade(&mut self, our: &Address) {
+        let now = get_now();
+        for user in std::mem::take(&mut self.legacy_banned_users) {
+            self.bans.insert(BanEntry {
+                user,
+                kind: BanKind::Ban,
+                reason: None,
+                issued_by: our.node.clone(),
+                issued_at: now,
+                expires_at: None,
+            });
+        }
+        // replies used to always answer the top-level post
+        for post in self.posts.values_mut() {
+            if post.parent_id.is_none() && post.thread_id.is_some() {
+                post.parent_id = post.thread_id;
+                post.depth = 1;
             }
-        });
-        
-        // Take up to 30 most recent top-level posts (including sticky posts)
-        let top_posts: Vec<PublicForumPost> = public_posts.into_iter()
-            .filter(|post| post.thread_id.is_none())
-            .take(30)
-            .collect();
-        
-        let upd = ForumUpdate::TopPosts(top_posts);
-        update_subscriber(AppUpdate::Forum(upd), &subscriber_node, our, service)?;
-        
+            let excess = post.edit_history.len().saturating_sub(MAX_EDIT_HISTORY);
+            post.edit_history.drain(..excess);
+        }
+    }
+
+    fn handle_subscribe(&mut self, subscriber_node: String, our: &Address, service: &Service) -> anyhow::Result<()> {
+        self.sync_feeds(our, service)?;
+        self.first_seen.entry(subscriber_node.clone()).or_insert_with(get_now);
+        if self.bans.prune_expired(get_now()) {
+            self.send_banned_users_update(our, service)?;
+        }
+
         // Send banned users list
-        let banned_users_update = ForumUpdate::BannedUsers(self.banned_users.iter().cloned().collect());
+        let banned_users_update = ForumUpdate::BannedUsers(self.bans.banned_users());
         update_subscriber(AppUpdate::Forum(banned_users_update), &subscriber_node, our, service)?;
+        let muted_users_update = ForumUpdate::MutedUsers(self.bans.muted_users());
+        update_subscriber(AppUpdate::Forum(muted_users_update), &subscriber_node, our, service)?;
+        if self.node_is_banned(&subscriber_node, get_now(), service) {
+            return Ok(());
+        }
+
+        let (top_posts, _) = self.list_posts(None, DEFAULT_PAGE_SIZE, ForumSort::New, None);
+        let upd = ForumUpdate::TopPosts(top_posts);
+        update_subscriber(AppUpdate::Forum(upd), &subscriber_node, our, service)?;
+        if self.feeds_bound == Some(true) {
+            let feed_upd = ForumUpdate::FeedUrl {
+                thread_id: None,
+                url: feed_url(our, &forum_feed_path(service)),
+            };
+            update_subscriber(AppUpdate::Forum(feed_upd), &subscriber_node, our, service)?;
+        }
+        if self.has_permission(&subscriber_node, our, ModeratorPermission::BanUsers) {
+            update_subscriber(AppUpdate::Forum(ForumUpdate::Bans(self.bans.entries())), &subscriber_node, our, service)?;
+        }
+
+        update_subscriber(AppUpdate::Forum(self.categories_update()), &subscriber_node, our, service)?;
+        update_subscriber(AppUpdate::Forum(self.moderators_update()), &subscriber_node, our, service)?;
         
         Ok(())
     }
 
     fn handle_request(&mut self, from: String, req: ForumRequest, our: &Address, service: &Service) -> anyhow::Result<()> {
+        self.sync_feeds(our, service)?;
+        let now = get_now();
+        if self.bans.prune_expired(now) {
+            self.send_banned_users_update(our, service)?;
+        }
+        // banned nodes can't do anything, muted nodes can still read
+        if self.node_is_banned(&from, now, service) {
+            return Ok(());
+        }
         match req {
-            ForumRequest::CreatePost { .. } => {
-                if self.banned_users.contains(&from) {
+            ForumRequest::CreatePost { .. }
+            | ForumRequest::EditPost { .. }
+            | ForumRequest::ReportPost { .. }
+            | ForumRequest::Vote { .. }
+            | ForumRequest::VotePoll { .. } => {
+                if self.node_is_restricted(&from, now, service) {
                     return Ok(());
                 }
             }
             _ => {}
         }
+        self.announce_closed_polls(now, our, service)?;
 
         match req {
-            ForumRequest::CreatePost { mut text_contents, link, image_url, is_anon, thread_id } => {
+            ForumRequest::CreatePost { mut text_contents, link, image_url, is_anon, thread_id, parent_id, category, poll } => {
                 // Sanitize text_contents
                 text_contents = sanitize_text(text_contents);
-                
+
+                let poll = match poll.map(|poll| Poll::new(poll, now)).transpose() {
+                    Ok(poll) => poll,
+                    Err(reason) => {
+                        let upd = ForumUpdate::PostRejected { reason };
+                        return update_subscriber(AppUpdate::Forum(upd), &from, our, service);
+                    }
+                };
+
+                // Older clients only send thread_id, which meant replying to the top-level post
+                let parent_id = parent_id.or(thread_id);
+                let (thread_id, depth) = match parent_id {
+                    Some(parent_id) => {
+                        let Some(parent_post) = self.posts.get(&parent_id) else {
+                            return Ok(());
+                        };
+                        if parent_post.depth >= MAX_COMMENT_DEPTH {
+                            return Ok(());
+                        }
+                        (Some(parent_post.thread_id.unwrap_or(parent_post.id)), parent_post.depth + 1)
+                    }
+                    None => (None, 0),
+                };
+
+                // Categories only apply to top-level posts
+                let category = if thread_id.is_some() {
+                    None
+                } else {
+                    match self.check_category(category) {
+                        Ok(category) => category,
+                        Err(_) => return Ok(()),
+                    }
+                };
+
+                let verdict = match self.screen_post(&from, &text_contents, link.as_deref(), image_url.as_deref(), now, our) {
+                    Ok(verdict) => verdict,
+                    Err(reason) => {
+                        let upd = ForumUpdate::PostRejected { reason };
+                        return update_subscriber(AppUpdate::Forum(upd), &from, our, service);
+                    }
+                };
+
                 let post_id = self.next_post_id;
                 self.next_post_id += 1;
+                let pseudonym = is_anon.then(|| self.pseudonym_for(&from, service));
                 
                 let new_post = ForumPost {
                     id: post_id,
                     text_contents,
                     link,
                     image_url,
-                    author: from,
+                    author: from.clone(),
                     upvotes: 0,
                     downvotes: 0,
                     comments: Vec::new(),
@@ -260,16 +817,89 @@ impl ForumServiceState {
                     is_sticky: false,
                     is_anon,
                     thread_id,
+                    parent_id,
+                    depth,
+                    edited_at: None,
+                    edit_history: Vec::new(),
+                    category,
+                    poll,
+                    link_preview: None,
+                    pseudonym,
+                    deleted: false,
                 };
 
-                if let Some(thread_id) = thread_id {
-                    if let Some(parent_post) = self.posts.get_mut(&thread_id) {
-                        parent_post.comments.push(post_id);
+                match verdict {
+                    Verdict::Accept => self.publish_post(new_post, our, service)?,
+                    Verdict::Hold => {
+                        let held = new_post.to_public(true);
+                        self.held_posts.insert(post_id, new_post);
+                        update_subscriber(AppUpdate::Forum(ForumUpdate::PostHeld { post_id }), &from, our, service)?;
+                        for moderator in self.nodes_with_permission(our, ModeratorPermission::HandleReports) {
+                            update_subscriber(AppUpdate::Forum(ForumUpdate::HeldPost(held.clone())), &moderator, our, service)?;
+                        }
                     }
                 }
-
-                self.posts.insert(post_id, new_post.clone());
-                update_subscribers(AppUpdate::Forum(ForumUpdate::NewPost(new_post.to_public(true))), our, service)?;
+            }
+            ForumRequest::VotePoll { post_id, choices } => {
+                let Some(post) = self.posts.get_mut(&post_id) else {
+                    return Ok(());
+                };
+                let Some(poll) = post.poll.as_mut() else {
+                    return Ok(());
+                };
+                if let Err(reason) = poll.vote(&from, choices, now) {
+                    let upd = ForumUpdate::PostRejected { reason };
+                    return update_subscriber(AppUpdate::Forum(upd), &from, our, service);
+                }
+                update_subscribers(AppUpdate::Forum(ForumUpdate::UpdatedPost(post.to_public(true))), our, service)?;
+            }
+            ForumRequest::EditPost { post_id, text_contents, link, image_url } => {
+                if !self.posts.get(&post_id).is_some_and(|post| post.author == from) {
+                    return Ok(());
+                }
+                let text_contents = sanitize_text(text_contents);
+                // edits count against the same limits as new posts
+                let rejection = match self.screen_post(&from, &text_contents, link.as_deref(), image_url.as_deref(), now, our) {
+                    Ok(Verdict::Accept) => None,
+                    Ok(Verdict::Hold) => Some("edits that need review can't be applied".to_string()),
+                    Err(reason) => Some(reason),
+                };
+                if let Some(reason) = rejection {
+                    let upd = ForumUpdate::PostRejected { reason };
+                    return update_subscriber(AppUpdate::Forum(upd), &from, our, service);
+                }
+                if let Some(post) = self.posts.get_mut(&post_id) {
+                    self.search_index.remove(post_id, &post.searchable_text());
+                    post.edit_history.push(PostRevision {
+                        text_contents: std::mem::replace(&mut post.text_contents, text_contents),
+                        link: std::mem::replace(&mut post.link, link),
+                        image_url: std::mem::replace(&mut post.image_url, image_url),
+                        replaced_at: now,
+                    });
+                    let excess = post.edit_history.len().saturating_sub(MAX_EDIT_HISTORY);
+                    post.edit_history.drain(..excess);
+                    post.edited_at = Some(now);
+                    let link_changed = post.link_preview.as_ref().map(|preview| &preview.url) != post.link.as_ref();
+                    if link_changed {
+                        post.link_preview = None;
+                    }
+                    self.search_index.insert(post_id, &post.searchable_text());
+                    update_subscribers(AppUpdate::Forum(ForumUpdate::UpdatedPost(post.to_public(true))), our, service)?;
+                    let thread_id = post.thread_id;
+                    self.refresh_feeds(post_id, thread_id, our, service)?;
+                    if link_changed {
+                        self.requeue_link_preview(post_id, now);
+                    }
+                }
+            }
+            ForumRequest::GetPostHistory { post_id } => {
+                if let Some(post) = self.posts.get(&post_id) {
+                    let history_update = ForumUpdate::PostHistory {
+                        post_id,
+                        history: post.edit_history.clone(),
+                    };
+                    update_subscriber(AppUpdate::Forum(history_update), &from, our, service)?;
+                }
             }
             ForumRequest::Vote { post_id, is_upvote } => {
                 if let Some(post) = self.posts.get_mut(&post_id) {
@@ -306,37 +936,66 @@ impl ForumServiceState {
                     update_subscriber(AppUpdate::Forum(ForumUpdate::UpdatedPost(post.to_public(true))), &from, our, service)?;
                 }
             }
-            ForumRequest::BanUser { user } => {
-                if from == our.node {
-                    self.banned_users.insert(user);
-                    self.send_banned_users_update(our, service)?;
+            ForumRequest::BanUser { user, reason, duration } => {
+                if self.has_permission(&from, our, ModeratorPermission::BanUsers) {
+                    self.ban_user(new_ban(from, user, BanKind::Ban, reason, duration, get_now()), our, service)?;
+                }
+            }
+            ForumRequest::MuteUser { user, reason, duration } => {
+                if self.has_permission(&from, our, ModeratorPermission::BanUsers) {
+                    self.ban_user(new_ban(from, user, BanKind::Mute, reason, duration, get_now()), our, service)?;
                 }
             }
             ForumRequest::UnbanUser { user } => {
-                if from == our.node {
-                    self.banned_users.remove(&user);
+                if self.has_permission(&from, our, ModeratorPermission::BanUsers) {
+                    self.bans.remove(&user);
+                    self.record_moderation(from, ModerationAction::UnbanUser { user });
+                    self.send_banned_users_update(our, service)?;
+                }
+            }
+            ForumRequest::GetBan { user } => {
+                if self.has_permission(&from, our, ModeratorPermission::BanUsers) || from == user {
+                    let ban = self.bans.get(&user, now).cloned();
+                    update_subscriber(AppUpdate::Forum(ForumUpdate::BanLookup { user, ban }), &from, our, service)?;
+                }
+            }
+            ForumRequest::ExportBans => {
+                if self.has_permission(&from, our, ModeratorPermission::BanUsers) {
+                    update_subscriber(AppUpdate::Forum(ForumUpdate::Bans(self.bans.entries())), &from, our, service)?;
+                }
+            }
+            ForumRequest::ImportBans { bans } => {
+                if self.has_permission(&from, our, ModeratorPermission::BanUsers) {
+                    let bans = bans.into_iter().filter(|entry| self.can_restrict(&from, &entry.user, our)).collect();
+                    let count = self.bans.import(bans, now);
+                    self.record_moderation(from, ModerationAction::ImportBans { count });
                     self.send_banned_users_update(our, service)?;
                 }
             }
             ForumRequest::DeletePost { post_id } => {
-                if from == our.node {
-                    if let Some(removed_post) = self.posts.remove(&post_id) {
-                        // Remove the post ID from its parent's comments vector
-                        if let Some(thread_id) = removed_post.thread_id {
-                            if let Some(parent_post) = self.posts.get_mut(&thread_id) {
-                                parent_post.comments.retain(|&id| id != post_id);
-                            }
-                        }
-
-                        let delete_update = ForumUpdate::DeletedPost(post_id);
-                        update_subscribers(AppUpdate::Forum(delete_update), our, service)?;
+                let Some(post) = self.posts.get(&post_id) else {
+                    return Ok(());
+                };
+                if post.author == from {
+                    // an author can take back their own words, not the replies under them
+                    if post.comments.is_empty() {
+                        self.delete_post(post_id, our, service)?;
+                    } else {
+                        self.tombstone_post(post_id, our, service)?;
                     }
+                } else if self.has_permission(&from, our, ModeratorPermission::DeletePosts) {
+                    self.delete_post(post_id, our, service)?;
+                    self.record_moderation(from, ModerationAction::RemovePost { post_id });
                 }
             }
-            ForumRequest::CreateStickyPost { text_contents, link, image_url, is_anon } => {
-                if from == our.node {
+            ForumRequest::CreateStickyPost { text_contents, link, image_url, is_anon, category } => {
+                if self.has_permission(&from, our, ModeratorPermission::CreateStickyPosts) {
+                    let Ok(category) = self.check_category(category) else {
+                        return Ok(());
+                    };
                     let post_id = self.next_post_id;
                     self.next_post_id += 1;
+                    let pseudonym = is_anon.then(|| self.pseudonym_for(&from, service));
                     
                     let new_post = ForumPost {
                         id: post_id,
@@ -352,41 +1011,890 @@ impl ForumServiceState {
                         is_sticky: true,
                         is_anon,
                         thread_id: None,
+                        parent_id: None,
+                        depth: 0,
+                        edited_at: None,
+                        edit_history: Vec::new(),
+                        category,
+                        poll: None,
+                        link_preview: None,
+                        pseudonym,
                     };
 
-                    self.posts.insert(post_id, new_post.clone());
-                    update_subscribers(AppUpdate::Forum(ForumUpdate::NewPost(new_post.to_public(true))), our, service)?;
+                    self.publish_post(new_post, our, service)?;
                 }
             }
             ForumRequest::ToggleSticky { post_id } => {
-                if from == our.node {
+                if self.has_permission(&from, our, ModeratorPermission::StickyPosts) {
                     if let Some(post) = self.posts.get_mut(&post_id) {
                         post.is_sticky = !post.is_sticky;
+                        let is_sticky = post.is_sticky;
                         update_subscribers(AppUpdate::Forum(ForumUpdate::UpdatedPost(post.to_public(true))), our, service)?;
+                        self.record_moderation(from, ModerationAction::ToggleSticky { post_id, is_sticky });
                     }
                 }
             }
             ForumRequest::GetPostAuthor { post_id } => {
-                if from == our.node {
+                if self.has_permission(&from, our, ModeratorPermission::RevealAuthors) {
                     if let Some(post) = self.posts.get(&post_id) {
                         let author_update = ForumUpdate::PostAuthor {
                             post_id,
                             author: post.author.clone(),
                         };
                         update_subscriber(AppUpdate::Forum(author_update), &from, our, service)?;
+                        self.record_moderation(from, ModerationAction::RevealAuthor { post_id });
+                    }
+                }
+            }
+            ForumRequest::ListPosts { cursor, limit, sort, category } => {
+                let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+                let (posts, next_cursor) = self.list_posts(cursor, limit, sort.unwrap_or(ForumSort::New), category.as_deref());
+                let upd = ForumUpdate::PostsPage { posts, next_cursor };
+                update_subscriber(AppUpdate::Forum(upd), &from, our, service)?;
+            }
+            ForumRequest::ListComments { post_id, cursor, limit, sort } => {
+                let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+                let (comments, next_cursor) = self.list_comments(post_id, cursor, limit, sort);
+                let upd = ForumUpdate::CommentsPage { post_id, comments, next_cursor };
+                update_subscriber(AppUpdate::Forum(upd), &from, our, service)?;
+            }
+            ForumRequest::Search { query, cursor, limit } => {
+                let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+                let (posts, next_cursor) = self.search(&query, cursor, limit);
+                let upd = ForumUpdate::SearchResults { query, posts, next_cursor };
+                update_subscriber(AppUpdate::Forum(upd), &from, our, service)?;
+            }
+            ForumRequest::ReportPost { post_id, reason } => {
+                let Some(post) = self.posts.get(&post_id) else {
+                    return Ok(());
+                };
+                // one open report per node per post
+                if self.reports.iter().any(|r| r.post_id == post_id && r.reporter == from) {
+                    return Ok(());
+                }
+                if self.reports.len() >= MAX_OPEN_REPORTS {
+                    let reason = "the moderation queue is full, try again later".to_string();
+                    update_subscriber(AppUpdate::Forum(ForumUpdate::ReportRejected { post_id, reason }), &from, our, service)?;
+                    return Ok(());
+                }
+                let report = PostReport {
+                    id: self.next_report_id,
+                    post_id,
+                    reporter: from,
+                    reason: sanitize_text(reason).chars().take(MAX_REPORT_REASON_LENGTH).collect(),
+                    created_at: get_now(),
+                };
+                self.next_report_id += 1;
+                let queued = QueuedReport {
+                    report: report.clone(),
+                    post: post.to_public(true),
+                };
+                self.reports.push(report);
+                for moderator in self.nodes_with_permission(our, ModeratorPermission::HandleReports) {
+                    update_subscriber(AppUpdate::Forum(ForumUpdate::NewReport(queued.clone())), &moderator, our, service)?;
+                }
+            }
+            ForumRequest::GetModerationQueue => {
+                if self.has_permission(&from, our, ModeratorPermission::HandleReports) {
+                    let queue = ForumUpdate::ModerationQueue(self.moderation_queue());
+                    update_subscriber(AppUpdate::Forum(queue), &from, our, service)?;
+                }
+            }
+            ForumRequest::ResolveReport { report_id, resolution } => {
+                if !self.has_permission(&from, our, ModeratorPermission::HandleReports) {
+                    return Ok(());
+                }
+                let Some(report) = self.reports.iter().find(|r| r.id == report_id).cloned() else {
+                    return Ok(());
+                };
+                let allowed = match resolution {
+                    ReportResolution::Dismiss => true,
+                    ReportResolution::RemovePost => self.has_permission(&from, our, ModeratorPermission::DeletePosts),
+                    ReportResolution::BanAuthor => {
+                        self.posts.get(&report.post_id).is_some_and(|post| self.can_restrict(&from, &post.author, our))
+                            && self.has_permission(&from, our, ModeratorPermission::DeletePosts)
+                    }
+                };
+                if !allowed {
+                    return Ok(());
+                }
+                match resolution {
+                    ReportResolution::Dismiss => {
+                        self.remove_reports(|r| r.id == report_id, our, service)?;
+                        self.record_moderation(from, ModerationAction::DismissReport { report_id, post_id: report.post_id });
+                    }
+                    ReportResolution::RemovePost => {
+                        self.delete_post(report.post_id, our, service)?;
+                        self.record_moderation(from, ModerationAction::RemovePost { post_id: report.post_id });
+                    }
+                    ReportResolution::BanAuthor => {
+                        let Some(post) = self.posts.get(&report.post_id) else {
+                            return Ok(());
+                        };
+                        // without RevealAuthors an anonymous author is banned under their
+                        // pseudonym, so the node never shows up in ban lists or the audit log
+                        let user = if post.is_anon && !self.has_permission(&from, our, ModeratorPermission::RevealAuthors) {
+                            self.pseudonym_for(&post.author, service)
+                        } else {
+                            post.author.clone()
+                        };
+                        let reason = Some(report.reason.clone());
+                        self.ban_user(new_ban(from.clone(), user, BanKind::Ban, reason, None, get_now()), our, service)?;
+                        self.delete_post(report.post_id, our, service)?;
+                        self.record_moderation(from, ModerationAction::RemovePost { post_id: report.post_id });
+                    }
+                }
+            }
+            ForumRequest::GetAuditLog => {
+                if self.has_permission(&from, our, ModeratorPermission::ViewAuditLog) {
+                    let log = ForumUpdate::AuditLog(self.audit_log.clone());
+                    update_subscriber(AppUpdate::Forum(log), &from, our, service)?;
+                }
+            }
+            ForumRequest::SetModerator { node, permissions } => {
+                if from == our.node && node != our.node {
+                    self.moderators.insert(node.clone(), permissions.iter().cloned().collect());
+                    self.record_moderation(from, ModerationAction::SetModerator { node, permissions });
+                    update_subscribers(AppUpdate::Forum(self.moderators_update()), our, service)?;
+                }
+            }
+            ForumRequest::RemoveModerator { node } => {
+                if from == our.node && self.moderators.remove(&node).is_some() {
+                    self.record_moderation(from, ModerationAction::RemoveModerator { node });
+                    update_subscribers(AppUpdate::Forum(self.moderators_update()), our, service)?;
+                }
+            }
+            ForumRequest::SetSpamSettings(settings) => {
+                if from == our.node {
+                    let mut settings = settings;
+                    if let Err(reason) = settings.compile() {
+                        let upd = ForumUpdate::PostRejected { reason };
+                        return update_subscriber(AppUpdate::Forum(upd), &from, our, service);
+                    }
+                    self.spam_settings = settings;
+                    self.record_moderation(from.clone(), ModerationAction::UpdateSpamSettings);
+                    update_subscriber(AppUpdate::Forum(ForumUpdate::SpamSettings(self.spam_settings.clone())), &from, our, service)?;
+                }
+            }
+            ForumRequest::GetSpamSettings => {
+                if self.has_permission(&from, our, ModeratorPermission::HandleReports) {
+                    update_subscriber(AppUpdate::Forum(ForumUpdate::SpamSettings(self.spam_settings.clone())), &from, our, service)?;
+                }
+            }
+            ForumRequest::GetHeldPosts => {
+                if self.has_permission(&from, our, ModeratorPermission::HandleReports) {
+                    let mut held: Vec<&ForumPost> = self.held_posts.values().collect();
+                    held.sort_by_key(|post| post.id);
+                    let held = held.iter().map(|post| post.to_public(true)).collect();
+                    update_subscriber(AppUpdate::Forum(ForumUpdate::HeldPosts(held)), &from, our, service)?;
+                }
+            }
+            ForumRequest::ApproveHeldPost { post_id } => {
+                if self.has_permission(&from, our, ModeratorPermission::HandleReports) {
+                    if let Some(post) = self.held_posts.remove(&post_id) {
+                        // the reply target may have been deleted while the post was held
+                        let orphaned = post.parent_id.is_some_and(|parent_id| !self.posts.contains_key(&parent_id));
+                        if !orphaned {
+                            self.publish_post(post, our, service)?;
+                        }
+                        self.record_moderation(from, ModerationAction::ApproveHeldPost { post_id });
+                    }
+                }
+            }
+            ForumRequest::RejectHeldPost { post_id } => {
+                if self.has_permission(&from, our, ModeratorPermission::HandleReports)
+                    && self.held_posts.remove(&post_id).is_some()
+                {
+                    self.record_moderation(from, ModerationAction::RejectHeldPost { post_id });
+                }
+            }
+            ForumRequest::GetThreadFeed { post_id } => {
+                let is_top_level = self.posts.get(&post_id).is_some_and(|post| post.thread_id.is_none());
+                // feeds are unauthenticated, so only public forums have them
+                if !is_top_level || self.feeds_bound != Some(true) {
+                    return Ok(());
+                }
+                if self.thread_feeds.insert(post_id) {
+                    self.bind_thread_feed(post_id, our, service)?;
+                }
+                let upd = ForumUpdate::FeedUrl {
+                    thread_id: Some(post_id),
+                    url: feed_url(our, &thread_feed_path(service, post_id)),
+                };
+                update_subscriber(AppUpdate::Forum(upd), &from, our, service)?;
+            }
+            ForumRequest::AddCategory { name, description, color } => {
+                if from == our.node {
+                    let name = name.trim().to_string();
+                    if name.is_empty()
+                        || name.chars().count() > MAX_CATEGORY_NAME_LENGTH
+                        || self.categories.len() >= MAX_CATEGORIES
+                        || self.categories.iter().any(|c| c.name == name)
+                    {
+                        return Ok(());
+                    }
+                    self.categories.push(ForumCategory { name, description, color });
+                    update_subscribers(AppUpdate::Forum(self.categories_update()), our, service)?;
+                }
+            }
+            ForumRequest::RemoveCategory { name } => {
+                if from == our.node {
+                    self.categories.retain(|c| c.name != name);
+                    let mut uncategorized = Vec::new();
+                    for post in self.posts.values_mut() {
+                        if post.category.as_ref() == Some(&name) {
+                            post.category = None;
+                            uncategorized.push(post.to_public(true));
+                        }
+                    }
+                    for post in self.held_posts.values_mut() {
+                        if post.category.as_ref() == Some(&name) {
+                            post.category = None;
+                        }
+                    }
+                    update_subscribers(AppUpdate::Forum(self.categories_update()), our, service)?;
+                    for post in uncategorized {
+                        update_subscribers(AppUpdate::Forum(ForumUpdate::UpdatedPost(post)), our, service)?;
+                    }
+                }
+            }
+            ForumRequest::SetRequireCategory { require_category } => {
+                if from == our.node {
+                    self.require_category = require_category;
+                    update_subscribers(AppUpdate::Forum(self.categories_update()), our, service)?;
+                }
+            }
+            ForumRequest::SetPostCategory { post_id, category } => {
+                let Ok(category) = self.check_category(category) else {
+                    return Ok(());
+                };
+                if let Some(post) = self.posts.get_mut(&post_id) {
+                    if post.thread_id.is_some() || (from != our.node && from != post.author) {
+                        return Ok(());
                     }
+                    post.category = category;
+                    update_subscribers(AppUpdate::Forum(ForumUpdate::UpdatedPost(post.to_public(true))), our, service)?;
                 }
             }
         }
         Ok(())
     }
 
+    fn pseudonym_for(&self, node: &str, service: &Service) -> String {
+        pseudonym::pseudonym(&self.pseudonym_secret, &service.id.to_string(), node)
+    }
+
+    // Bans can name either a node or the pseudonym it posts under anonymously
+    fn node_is_banned(&self, node: &str, now: u64, service: &Service) -> bool {
+        self.bans.is_banned(node, now) || self.bans.is_banned(&self.pseudonym_for(node, service), now)
+    }
+
+    fn node_is_restricted(&self, node: &str, now: u64, service: &Service) -> bool {
+        self.bans.is_restricted(node, now) || self.bans.is_restricted(&self.pseudonym_for(node, service), now)
+    }
+
+    // Rate limits, account age and content rules. The host is exempt.
+    fn screen_post(&mut self, from: &str, text: &str, link: Option<&str>, image_url: Option<&str>, now: u64, our: &Address) -> Result<Verdict, String> {
+        if from == our.node {
+            return Ok(Verdict::Accept);
+        }
+        let first_seen = self.first_seen.get(from).cloned().unwrap_or(now);
+        if now.saturating_sub(first_seen) < self.spam_settings.min_account_age {
+            return Err("your node is too new to post here yet".to_string());
+        }
+        let verdict = self.spam_settings.check_content(text, link, image_url)?;
+        if !self.rate_limiter.try_acquire(from, now, &self.spam_settings) {
+            return Err("you are posting too quickly, try again shortly".to_string());
+        }
+        Ok(verdict)
+    }
+
+    fn schedule_poll_close(&mut self) {
+        self.next_poll_close = self.posts.values()
+            .filter_map(|post| post.poll.as_ref()?.pending_close())
+            .min();
+    }
+
+    // Hidden poll results become visible once the poll closes, so send those posts out again.
+    // Only looks through the posts when the earliest pending close has passed.
+    fn announce_closed_polls(&mut self, now: u64, our: &Address, service: &Service) -> anyhow::Result<bool> {
+        if self.next_poll_close.is_none_or(|closes_at| closes_at > now) {
+            return Ok(false);
+        }
+        let mut closed: Vec<PublicForumPost> = Vec::new();
+        for post in self.posts.values_mut() {
+            if post.poll.as_mut().is_some_and(|poll| poll.take_close_announcement(now)) {
+                closed.push(post.to_public(true));
+            }
+        }
+        self.schedule_poll_close();
+        let announced = !closed.is_empty();
+        for post in closed {
+            update_subscribers(AppUpdate::Forum(ForumUpdate::UpdatedPost(post)), our, service)?;
+        }
+        Ok(announced)
+    }
+
+    fn publish_post(&mut self, post: ForumPost, our: &Address, service: &Service) -> anyhow::Result<()> {
+        if let Some(parent_id) = post.parent_id {
+            if let Some(parent_post) = self.posts.get_mut(&parent_id) {
+                parent_post.comments.push(post.id);
+            }
+        }
+        self.search_index.insert(post.id, &post.searchable_text());
+        if let Some(closes_at) = post.poll.as_ref().and_then(|poll| poll.pending_close()) {
+            self.next_poll_close = Some(self.next_poll_close.map_or(closes_at, |next| next.min(closes_at)));
+        }
+        let public_post = post.to_public(true);
+        let (post_id, thread_id) = (post.id, post.thread_id);
+        self.posts.insert(post.id, post);
+        update_subscribers(AppUpdate::Forum(ForumUpdate::NewPost(public_post)), our, service)?;
+        self.refresh_feeds(post_id, thread_id, our, service)?;
+        self.queue_link_preview(post_id);
+        Ok(())
+    }
+
+    fn queue_link_preview(&mut self, post_id: u64) {
+        let has_link = self.posts.get(&post_id).is_some_and(|post| post.link.is_some());
+        if has_link && !self.pending_previews.contains(&post_id) {
+            self.pending_previews.push(post_id);
+        }
+    }
+
+    // Edits can change a link over and over, so a post is looked at again at most once per interval
+    fn requeue_link_preview(&mut self, post_id: u64, now: u64) {
+        self.preview_requeues.retain(|_, &mut queued_at| now.saturating_sub(queued_at) < PREVIEW_REQUEUE_INTERVAL);
+        if self.preview_requeues.contains_key(&post_id) {
+            return;
+        }
+        self.preview_requeues.insert(post_id, now);
+        self.queue_link_preview(post_id);
+    }
+
+    // Returns whether any queued post was looked at, so the caller knows to save.
+    // Sends new links to linkpreview and attaches whatever it has finished.
+    fn fetch_link_previews(&mut self, our: &Address, service: &Service) -> anyhow::Result<bool> {
+        let now = get_now();
+        let requester = service.id.to_string();
+        let mut changed = false;
+        if !self.previews_in_flight.is_empty() {
+            for fetched in preview::take_previews(our, &requester)? {
+                self.previews_in_flight.remove(&fetched.link);
+                let link = fetched.link.clone();
+                match fetched.into_preview(now) {
+                    Some(link_preview) => self.attach_link_preview(&link, link_preview, our, service)?,
+                    None => {
+                        self.preview_failures.retain(|_, &mut failed_at| now.saturating_sub(failed_at) < PREVIEW_RETRY_AFTER);
+                        self.preview_failures.insert(link.clone(), now);
+                        self.pending_previews.retain(|post_id| {
+                            self.posts.get(post_id).and_then(|post| post.link.as_ref()) != Some(&link)
+                        });
+                    }
+                }
+                changed = true;
+            }
+            // linkpreview restarted or never answered, those links can be sent again
+            self.previews_in_flight.retain(|_, &mut sent_at| now.saturating_sub(sent_at) < PREVIEW_FETCH_EXPIRY);
+        }
+
+        let pending = std::mem::take(&mut self.pending_previews);
+        for post_id in pending {
+            let Some(post) = self.posts.get(&post_id) else {
+                changed = true;
+                continue;
+            };
+            let Some(link) = post.link.clone() else {
+                changed = true;
+                continue;
+            };
+            if self.previews_in_flight.contains_key(&link) {
+                self.pending_previews.push(post_id);
+                continue;
+            }
+            let cached = self.posts.values()
+                .filter_map(|post| post.link_preview.as_ref())
+                .find(|preview| preview.url == link)
+                .cloned();
+            if let Some(link_preview) = cached {
+                self.pending_previews.push(post_id);
+                self.attach_link_preview(&link, link_preview, our, service)?;
+                changed = true;
+                continue;
+            }
+            // a link that just failed isn't tried again on every edit
+            let recently_failed = self.preview_failures.get(&link)
+                .is_some_and(|&failed_at| now.saturating_sub(failed_at) < PREVIEW_RETRY_AFTER);
+            let author = post.author.clone();
+            if recently_failed || !self.take_preview_fetch(&author, now) {
+                changed = true;
+                continue;
+            }
+            preview::request_preview(our, &requester, &link)?;
+            self.previews_in_flight.insert(link, now);
+            self.pending_previews.push(post_id);
+        }
+        Ok(changed)
+    }
+
+    // Each author, and the service as a whole, gets a handful of fetches per window
+    fn take_preview_fetch(&mut self, author: &str, now: u64) -> bool {
+        self.preview_fetches.retain(|(fetched_at, _)| now.saturating_sub(*fetched_at) < PREVIEW_FETCH_WINDOW);
+        let by_author = self.preview_fetches.iter().filter(|(_, fetched_by)| fetched_by == author).count();
+        if by_author >= MAX_PREVIEW_FETCHES_PER_AUTHOR || self.preview_fetches.len() >= MAX_PREVIEW_FETCHES {
+            return false;
+        }
+        self.preview_fetches.push((now, author.to_string()));
+        true
+    }
+
+    // The host fetches link metadata once so subscribers never contact the linked site.
+    // Sent as a follow-up update, so a slow site doesn't hold back the post itself.
+    fn attach_link_preview(&mut self, link: &str, link_preview: LinkPreview, our: &Address, service: &Service) -> anyhow::Result<()> {
+        let pending = std::mem::take(&mut self.pending_previews);
+        for post_id in pending {
+            let Some(post) = self.posts.get_mut(&post_id) else {
+                continue;
+            };
+            if post.link.as_deref() != Some(link) {
+                self.pending_previews.push(post_id);
+                continue;
+            }
+            post.link_preview = Some(link_preview.clone());
+            update_subscribers(AppUpdate::Forum(ForumUpdate::UpdatedPost(post.to_public(true))), our, service)?;
+        }
+        Ok(())
+    }
+
+    // Feeds are served without authentication, so they only exist while the
+    // service is public. Binds or unbinds them whenever that changes.
+    fn sync_feeds(&mut self, our: &Address, service: &Service) -> anyhow::Result<()> {
+        let is_public = matches!(service.meta.access, ServiceAccess::Public);
+        if self.feeds_bound == Some(is_public) {
+            return Ok(());
+        }
+        if is_public {
+            self.bind_feeds(our, service)?;
+        } else if self.feeds_bound.is_some() {
+            // bindings don't survive a restart, so there is only something to
+            // take down if this process bound it
+            self.unbind_feeds(service)?;
+        }
+        self.feeds_bound = Some(is_public);
+        Ok(())
+    }
+
+    fn bind_feeds(&self, our: &Address, service: &Service) -> anyhow::Result<()> {
+        self.bind_forum_feed(our, service)?;
+        for post_id in &self.thread_feeds {
+            self.bind_thread_feed(*post_id, our, service)?;
+        }
+        Ok(())
+    }
+
+    fn unbind_feeds(&self, service: &Service) -> anyhow::Result<()> {
+        let mut http_server = server::HttpServer::new(5);
+        http_server.unbind_http_path(forum_feed_path(service))?;
+        for post_id in &self.thread_feeds {
+            http_server.unbind_http_path(thread_feed_path(service, *post_id))?;
+        }
+        Ok(())
+    }
+
+    // Rebuilds whichever feeds a changed post shows up in
+    fn refresh_feeds(&self, post_id: u64, thread_id: Option<u64>, our: &Address, service: &Service) -> anyhow::Result<()> {
+        if self.feeds_bound != Some(true) {
+            return Ok(());
+        }
+        if thread_id.is_none() {
+            self.bind_forum_feed(our, service)?;
+        }
+        let root_id = thread_id.unwrap_or(post_id);
+        if self.thread_feeds.contains(&root_id) {
+            self.bind_thread_feed(root_id, our, service)?;
+        }
+        Ok(())
+    }
+
+    fn bind_forum_feed(&self, our: &Address, service: &Service) -> anyhow::Result<()> {
+        let mut newest: Vec<&ForumPost> = self.posts.values()
+            .filter(|post| post.thread_id.is_none())
+            .collect();
+        newest.sort_by(|a, b| b.created_at.cmp(&a.created_at).then(b.id.cmp(&a.id)));
+        let entries: Vec<PublicForumPost> = newest.iter()
+            .take(FEED_LENGTH)
+            .map(|post| post.to_public(true))
+            .collect();
+        let path = forum_feed_path(service);
+        let info = FeedInfo {
+            id: format!("urn:dartfrog:{}", service.id),
+            title: service.id.name.clone(),
+            author: our.node.clone(),
+            self_path: feed_url(our, &path),
+            alternate_path: format!("/{}/df/service/{}", our.process, service.id),
+        };
+        bind_feed(&path, feed::atom_feed(&info, &entries))
+    }
+
+    fn bind_thread_feed(&self, post_id: u64, our: &Address, service: &Service) -> anyhow::Result<()> {
+        let Some(root) = self.posts.get(&post_id) else {
+            return Ok(());
+        };
+        // newest replies first, wherever they sit in the thread
+        let mut replies: Vec<&ForumPost> = self.posts.values()
+            .filter(|post| post.thread_id == Some(post_id))
+            .collect();
+        replies.sort_by(|a, b| b.created_at.cmp(&a.created_at).then(b.id.cmp(&a.id)));
+        let mut entries: Vec<PublicForumPost> = replies.iter()
+            .take(FEED_LENGTH)
+            .map(|post| post.to_public(true))
+            .collect();
+        entries.push(root.to_public(true));
+        let path = thread_feed_path(service, post_id);
+        let info = FeedInfo {
+            id: format!("urn:dartfrog:{}:thread:{}", service.id, post_id),
+            title: format!("{} #{}", service.id.name, post_id),
+            author: our.node.clone(),
+            self_path: feed_url(our, &path),
+            alternate_path: format!("/{}/df/service/{}", our.process, service.id),
+        };
+        bind_feed(&path, feed::atom_feed(&info, &entries))
+    }
+
+    // The host holds every permission; moderators hold only what the host granted them
+    fn has_permission(&self, node: &str, our: &Address, permission: ModeratorPermission) -> bool {
+        node == our.node
+            || self.moderators.get(node).is_some_and(|permissions| permissions.contains(&permission))
+    }
+
+    // Moderators can ban and mute regular users, only the host can restrict a moderator
+    fn can_restrict(&self, node: &str, user: &str, our: &Address) -> bool {
+        user != our.node
+            && self.has_permission(node, our, ModeratorPermission::BanUsers)
+            && (node == our.node || !self.moderators.contains_key(user))
+    }
+
+    fn nodes_with_permission(&self, our: &Address, permission: ModeratorPermission) -> Vec<String> {
+        let mut nodes = vec![our.node.clone()];
+        nodes.extend(
+            self.moderators.iter()
+                .filter(|(node, permissions)| **node != our.node && permissions.contains(&permission))
+                .map(|(node, _)| node.clone())
+        );
+        nodes
+    }
+
+    fn moderators_update(&self) -> ForumUpdate {
+        ForumUpdate::Moderators(
+            self.moderators.iter()
+                .map(|(node, permissions)| Moderator {
+                    node: node.clone(),
+                    permissions: permissions.iter().cloned().collect(),
+                })
+                .collect()
+        )
+    }
+
+    fn record_moderation(&mut self, actor: String, action: ModerationAction) {
+        self.audit_log.push(AuditLogEntry {
+            actor,
+            action,
+            time: get_now(),
+        });
+        if self.audit_log.len() > MAX_AUDIT_LOG_ENTRIES {
+            let excess = self.audit_log.len() - MAX_AUDIT_LOG_ENTRIES;
+            self.audit_log.drain(..excess);
+        }
+    }
+
+    // Open reports, oldest first
+    fn moderation_queue(&self) -> Vec<QueuedReport> {
+        self.reports.iter()
+            .filter_map(|report| {
+                let post = self.posts.get(&report.post_id)?;
+                Some(QueuedReport {
+                    report: report.clone(),
+                    post: post.to_public(true),
+                })
+            })
+            .collect()
+    }
+
+    fn rebuild_search_index(&mut self) {
+        self.search_index = SearchIndex::default();
+        for post in self.posts.values() {
+            self.search_index.insert(post.id, &post.searchable_text());
+        }
+    }
+
+    // Posts matching more of the query terms rank first, then those mentioning them more often, then newer posts
+    fn search(&self, query: &str, cursor: Option<Cursor>, limit: usize) -> (Vec<PublicForumPost>, Option<Cursor>) {
+        let terms = search::tokenize(query);
+        let matches = self.search_index.lookup(&terms);
+        let ranked: Vec<(&ForumPost, usize, usize)> = matches.into_iter()
+            .filter_map(|(id, matched_terms)| {
+                let post = self.posts.get(&id)?;
+                let occurrences = search::count_occurrences(&post.searchable_text(), &terms);
+                Some((post, matched_terms, occurrences))
+            })
+            .collect();
+        let mut keyed: Vec<(&ForumPost, Cursor)> = ranked.into_iter()
+            .map(|(post, matched_terms, occurrences)| {
+                let mut key = vec![-(matched_terms as f64), -(occurrences as f64)];
+                key.extend(sort_key(post, ForumSort::New));
+                (post, Cursor(key))
+            })
+            .collect();
+        keyed.sort_by(|a, b| a.1.total_cmp(&b.1));
+        paginate(keyed, cursor, limit)
+    }
+
+    fn categories_update(&self) -> ForumUpdate {
+        ForumUpdate::Categories {
+            categories: self.categories.clone(),
+            require_category: self.require_category,
+        }
+    }
+
+    // A top-level post's category must be one the host defined,
+    // and may only be left out when the forum doesn't require one
+    fn check_category(&self, category: Option<String>) -> Result<Option<String>, ()> {
+        match category {
+            Some(name) if self.categories.iter().any(|c| c.name == name) => Ok(Some(name)),
+            Some(_) => Err(()),
+            None if self.require_category && !self.categories.is_empty() => Err(()),
+            None => Ok(None),
+        }
+    }
+
+    // Drops matching reports and takes them off every moderator's queue
+    fn remove_reports(&mut self, matches: impl Fn(&PostReport) -> bool, our: &Address, service: &Service) -> anyhow::Result<()> {
+        let (resolved, open): (Vec<PostReport>, Vec<PostReport>) = std::mem::take(&mut self.reports)
+            .into_iter()
+            .partition(|r| matches(r));
+        self.reports = open;
+        if resolved.is_empty() {
+            return Ok(());
+        }
+        let moderators = self.nodes_with_permission(our, ModeratorPermission::HandleReports);
+        for report in resolved {
+            for moderator in &moderators {
+                update_subscriber(AppUpdate::Forum(ForumUpdate::ResolvedReport(report.id)), moderator, our, service)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Clears a post's contents and author but keeps it, so the replies under it stay
+    fn tombstone_post(&mut self, post_id: u64, our: &Address, service: &Service) -> anyhow::Result<()> {
+        let Some(post) = self.posts.get_mut(&post_id) else {
+            return Ok(());
+        };
+        self.search_index.remove(post_id, &post.searchable_text());
+        post.text_contents = "[deleted]".to_string();
+        post.link = None;
+        post.image_url = None;
+        post.author = String::new();
+        post.pseudonym = None;
+        post.poll = None;
+        post.link_preview = None;
+        post.edit_history.clear();
+        post.deleted = true;
+        let upd = ForumUpdate::UpdatedPost(post.to_public(true));
+        let thread_id = post.thread_id;
+        self.remove_reports(|r| r.post_id == post_id, our, service)?;
+        update_subscribers(AppUpdate::Forum(upd), our, service)?;
+        self.refresh_feeds(post_id, thread_id, our, service)
+    }
+
+    // Removes a post along with every reply nested underneath it
+    fn delete_post(&mut self, post_id: u64, our: &Address, service: &Service) -> anyhow::Result<()> {
+        let Some(removed_post) = self.posts.remove(&post_id) else {
+            return Ok(());
+        };
+        self.search_index.remove(post_id, &removed_post.searchable_text());
+        self.remove_reports(|r| r.post_id == post_id, our, service)?;
+        if let Some(parent_id) = removed_post.parent_id {
+            if let Some(parent_post) = self.posts.get_mut(&parent_id) {
+                parent_post.comments.retain(|&id| id != post_id);
+            }
+        }
+        if removed_post.thread_id.is_none() && self.thread_feeds.remove(&post_id) && self.feeds_bound == Some(true) {
+            let mut http_server = server::HttpServer::new(5);
+            http_server.unbind_http_path(thread_feed_path(service, post_id))?;
+        }
+        let mut pending = removed_post.comments;
+        while let Some(child_id) = pending.pop() {
+            if let Some(child) = self.posts.remove(&child_id) {
+                self.search_index.remove(child_id, &child.searchable_text());
+                self.remove_reports(|r| r.post_id == child_id, our, service)?;
+                pending.extend(child.comments);
+                update_subscribers(AppUpdate::Forum(ForumUpdate::DeletedPost(child_id)), our, service)?;
+            }
+        }
+        update_subscribers(AppUpdate::Forum(ForumUpdate::DeletedPost(post_id)), our, service)?;
+        self.refresh_feeds(post_id, removed_post.thread_id, our, service)
+    }
+
+    // Top-level posts. Sticky posts stay pinned above new and hot listings.
+    fn list_posts(&self, cursor: Option<Cursor>, limit: usize, sort: ForumSort, category: Option<&str>) -> (Vec<PublicForumPost>, Option<Cursor>) {
+        let cutoff = match sort {
+            ForumSort::Top(window) => window.cutoff(get_now()),
+            _ => 0,
+        };
+        let pin_sticky = matches!(sort, ForumSort::New | ForumSort::Hot);
+        let mut top_posts: Vec<(&ForumPost, Cursor)> = self.posts.values()
+            .filter(|post| post.thread_id.is_none() && post.created_at >= cutoff)
+            .filter(|post| category.is_none() || post.category.as_deref() == category)
+            .map(|post| {
+                let mut key = Vec::new();
+                if pin_sticky {
+                    key.push(if post.is_sticky { 0.0 } else { 1.0 });
+                }
+                key.extend(sort_key(post, sort));
+                (post, Cursor(key))
+            })
+            .collect();
+        top_posts.sort_by(|a, b| a.1.total_cmp(&b.1));
+        paginate(top_posts, cursor, limit)
+    }
+
+    // Every reply under a post, depth first. parent_id and depth let the client
+    // rebuild the tree. Siblings are oldest first unless a sort is given.
+    // A reply's key is its parent's key followed by its own sibling key, so
+    // ordering by key is the depth-first order.
+    fn list_comments(&self, post_id: u64, cursor: Option<Cursor>, limit: usize, sort: Option<ForumSort>) -> (Vec<PublicForumPost>, Option<Cursor>) {
+        let Some(root) = self.posts.get(&post_id) else {
+            return (vec![], None);
+        };
+        let mut flattened: Vec<(&ForumPost, Cursor)> = Vec::new();
+        let mut stack = self.keyed_children(root, &[], sort);
+        while let Some((post, key)) = stack.pop() {
+            stack.extend(self.keyed_children(post, &key.0, sort));
+            flattened.push((post, key));
+        }
+        paginate(flattened, cursor, limit)
+    }
+
+    // A post's replies with their keys, last first so they pop off a stack in order
+    fn keyed_children(&self, post: &ForumPost, parent_key: &[f64], sort: Option<ForumSort>) -> Vec<(&ForumPost, Cursor)> {
+        let mut children: Vec<(&ForumPost, Cursor)> = post.comments.iter()
+            .filter_map(|id| self.posts.get(id))
+            .map(|child| {
+                let mut key = parent_key.to_vec();
+                match sort {
+                    Some(sort) => key.extend(sort_key(child, sort)),
+                    None => key.extend([child.created_at as f64, child.id as f64]),
+                }
+                (child, Cursor(key))
+            })
+            .collect();
+        children.sort_by(|a, b| b.1.total_cmp(&a.1));
+        children
+    }
+
+    fn ban_user(&mut self, entry: BanEntry, our: &Address, service: &Service) -> anyhow::Result<()> {
+        if !self.can_restrict(&entry.issued_by, &entry.user, our) {
+            return Ok(());
+        }
+        let action = ModerationAction::BanUser {
+            user: entry.user.clone(),
+            kind: entry.kind,
+            reason: entry.reason.clone(),
+            expires_at: entry.expires_at,
+        };
+        self.record_moderation(entry.issued_by.clone(), action);
+        self.bans.insert(entry);
+        self.send_banned_users_update(our, service)
+    }
+
     fn send_banned_users_update(&self, our: &Address, service: &Service) -> anyhow::Result<()> {
-        let banned_users_update = ForumUpdate::BannedUsers(self.banned_users.iter().cloned().collect());
-        update_subscribers(AppUpdate::Forum(banned_users_update), our, service)
+        let banned_users_update = ForumUpdate::BannedUsers(self.bans.banned_users());
+        update_subscribers(AppUpdate::Forum(banned_users_update), our, service)?;
+        let muted_users_update = ForumUpdate::MutedUsers(self.bans.muted_users());
+        update_subscribers(AppUpdate::Forum(muted_users_update), our, service)?;
+        for moderator in self.nodes_with_permission(our, ModeratorPermission::BanUsers) {
+            update_subscriber(AppUpdate::Forum(ForumUpdate::Bans(self.bans.entries())), &moderator, our, service)?;
+        }
+        Ok(())
+    }
+}
+
+fn forum_feed_path(service: &Service) -> String {
+    format!("/feed/{}", service.id.name)
+}
+
+fn thread_feed_path(service: &Service, post_id: u64) -> String {
+    format!("/feed/{}/{}", service.id.name, post_id)
+}
+
+// http-server serves our bindings under the process id
+fn feed_url(our: &Address, path: &str) -> String {
+    format!("/{}{}", our.process, path)
+}
+
+// Feeds are static content, rebound whenever the posts they show change
+fn bind_feed(path: &str, feed: String) -> anyhow::Result<()> {
+    let config = server::HttpBindingConfig::default()
+        .authenticated(false)
+        .static_content(Some(LazyLoadBlob {
+            mime: Some(FEED_MIME.to_string()),
+            bytes: feed.into_bytes(),
+        }));
+    let mut http_server = server::HttpServer::new(5);
+    http_server.bind_http_path(path, config)?;
+    Ok(())
+}
+
+// Where a listing left off: the sort key of the last post handed out, which
+// always ends in the post's id. Listings are ordered by ascending key, and the
+// next page starts after this position, so a deleted post or a changed score
+// can't stop or rewind pagination.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Cursor(Vec<f64>);
+
+impl Cursor {
+    fn total_cmp(&self, other: &Cursor) -> std::cmp::Ordering {
+        for (a, b) in self.0.iter().zip(other.0.iter()) {
+            let ordering = a.total_cmp(b);
+            if ordering != std::cmp::Ordering::Equal {
+                return ordering;
+            }
+        }
+        self.0.len().cmp(&other.0.len())
     }
 }
 
+// Values are negated so that larger ranks and newer posts come first
+fn sort_key(post: &ForumPost, sort: ForumSort) -> Vec<f64> {
+    let mut key = match sort {
+        ForumSort::New => vec![],
+        ForumSort::Hot => vec![-post.hot_rank()],
+        ForumSort::Top(_) => vec![-(post.score() as f64)],
+        ForumSort::Controversial => vec![-post.controversy()],
+    };
+    key.extend([-(post.created_at as f64), -(post.id as f64)]);
+    key
+}
+
+
+// Returns the page of posts following the cursor, and the cursor for the page after it
+fn paginate(sorted: Vec<(&ForumPost, Cursor)>, cursor: Option<Cursor>, limit: usize) -> (Vec<PublicForumPost>, Option<Cursor>) {
+    let start = match &cursor {
+        Some(cursor) => sorted.partition_point(|(_, key)| key.total_cmp(cursor) != std::cmp::Ordering::Greater),
+        None => 0,
+    };
+    let end = (start + limit).min(sorted.len());
+    let page: Vec<PublicForumPost> = sorted[start..end].iter()
+        .map(|(post, _)| post.to_public(true))
+        .collect();
+    let next_cursor = if end < sorted.len() && end > start {
+        Some(sorted[end - 1].1.clone())
+    } else {
+        None
+    };
+    (page, next_cursor)
+}
+
 fn sanitize_text(text: String) -> String {
     // Replace continuous whitespace areas
     let sanitized = regex::Regex::new(r"\s+")
@@ -408,6 +1916,14 @@ fn sanitize_text(text: String) -> String {
     sanitized
 }
 
+// Links go to linkpreview from here rather than while handling the request,
+// and finished previews are picked up whenever the loop next comes around
+fn handle_link_previews(our: &Address, state: &mut AppState) {
+    for_each_service(our, state, "fetching link previews", |app_service, service| {
+        app_service.fetch_link_previews(our, service)
+    });
+}
+
 call_init!(init);
 fn init(our: Address) {
     println!("init forum");
@@ -433,11 +1949,124 @@ fn init(our: Address) {
         .expect("failed to bind ws");
 
     loop {
+        handle_timers(&our, &mut state);
         match provider_handle_message(&our, &mut state.provider) {
             Ok(()) => {}
             Err(e) => {
                 println!("forum error handling message: {:?}", e);
             }
         };
+        handle_link_previews(&our, &mut state);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn our() -> Address {
+        Address::new("host.os", ("forum", "dartfrog", "gliderlabs.os"))
+    }
+
+    // Loads saved json the way init does, after default_load_service
+    fn load(saved: &str) -> ForumServiceState {
+        let mut state: ForumServiceState = serde_json::from_str(saved).unwrap();
+        state.upgrade(&our());
+        state
+    }
+
+    // The same json that default_save_service writes, loaded back through init's path
+    fn reload(state: &ForumServiceState) -> ForumServiceState {
+        load(&serde_json::to_string(state).unwrap())
+    }
+
+    // Sets serialize in hash order, so they're compared as values and
+    // everything else as json
+    fn assert_same(loaded: &ForumServiceState, state: &ForumServiceState) {
+        assert_eq!(loaded.thread_feeds, state.thread_feeds);
+        assert_eq!(loaded.moderators, state.moderators);
+        let strip = |state: &ForumServiceState| {
+            let mut value = serde_json::to_value(state).unwrap();
+            let object = value.as_object_mut().unwrap();
+            object.remove("thread_feeds");
+            object.remove("moderators");
+            value
+        };
+        assert_eq!(strip(loaded), strip(state));
+    }
+
+    // An upgraded state has to come back the same after a save and load
+    fn assert_saved(state: &ForumServiceState) -> ForumServiceState {
+        let loaded = reload(state);
+        assert_same(&loaded, state);
+        loaded
+    }
+
+    fn baseline_post(id: u64, thread_id: Option<u64>, comments: &str) -> String {
+        let thread_id = thread_id.map_or("null".to_string(), |id| id.to_string());
+        format!(
+            r#"{{"id":{id},"text_contents":"post {id}","link":null,"image_url":null,"author":"a.os","upvotes":1,"downvotes":0,"comments":[{comments}],"created_at":{id},"voted_users":{{"b.os":true}},"is_sticky":false,"is_anon":false,"thread_id":{thread_id}}}"#
+        )
+    }
+
+    #[test]
+    fn baseline_save_loads_and_upgrades() {
+        let saved = format!(
+            r#"{{"posts":{{"1":{},"2":{}}},"next_post_id":3,"banned_users":["b.os"]}}"#,
+            baseline_post(1, None, "2"),
+            baseline_post(2, Some(1), ""),
+        );
+        let state = load(&saved);
+        let loaded = assert_saved(&state);
+        assert_eq!(loaded.next_post_id, 3);
+        assert_eq!(loaded.posts[&1].parent_id, None);
+        assert_eq!(loaded.posts[&1].depth, 0);
+        assert_eq!(loaded.posts[&2].parent_id, Some(1));
+        assert_eq!(loaded.posts[&2].depth, 1);
+        assert_eq!(loaded.posts[&2].text_contents, "post 2");
+        assert!(loaded.bans.is_banned("b.os", get_now()));
+        assert_eq!(loaded.bans.entries()[0].issued_by, "host.os");
+        assert!(serde_json::to_value(&loaded).unwrap().get("banned_users").is_none());
+        // made up on load, and kept by the save init does afterwards
+        assert_eq!(loaded.pseudonym_secret, state.pseudonym_secret);
+        assert_eq!(loaded.next_report_id, 1);
+    }
+
+    #[test]
+    fn long_edit_history_is_trimmed_on_load() {
+        let mut state = ForumServiceState::new();
+        let saved = baseline_post(1, None, "");
+        let mut post: ForumPost = serde_json::from_str(&saved).unwrap();
+        post.edit_history = (0..MAX_EDIT_HISTORY as u64 + 5)
+            .map(|i| PostRevision {
+                text_contents: i.to_string(),
+                link: None,
+                image_url: None,
+                replaced_at: i,
+            })
+            .collect();
+        state.posts.insert(1, post);
+        let loaded = reload(&state);
+        let history = &loaded.posts[&1].edit_history;
+        assert_eq!(history.len(), MAX_EDIT_HISTORY);
+        assert_eq!(history[0].text_contents, "5");
+        assert_same(&reload(&loaded), &loaded);
+    }
+
+    #[test]
+    fn nested_replies_are_not_flattened_on_load() {
+        let saved = format!(
+            r#"{{"posts":{{"1":{},"2":{},"3":{}}},"next_post_id":4}}"#,
+            baseline_post(1, None, "2"),
+            baseline_post(2, Some(1), "3"),
+            baseline_post(3, Some(1), ""),
+        );
+        let mut state: ForumServiceState = serde_json::from_str(&saved).unwrap();
+        let reply = state.posts.get_mut(&3).unwrap();
+        reply.parent_id = Some(2);
+        reply.depth = 2;
+        let loaded = assert_saved(&reload(&state));
+        assert_eq!(loaded.posts[&3].parent_id, Some(2));
+        assert_eq!(loaded.posts[&3].depth, 2);
+    }
+}
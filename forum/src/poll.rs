@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+
+use serde::{Serialize, Deserialize};
+
+const MIN_POLL_OPTIONS: usize = 2;
+const MAX_POLL_OPTIONS: usize = 10;
+const MAX_QUESTION_LENGTH: usize = 300;
+const MAX_OPTION_LENGTH: usize = 200;
+
+// What a client sends to create a poll
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewPoll {
+    pub question: String,
+    pub options: Vec<String>,
+    pub multiple_choice: bool,
+    pub closes_at: Option<u64>,
+    pub hide_results_until_close: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Poll {
+    question: String,
+    options: Vec<String>,
+    multiple_choice: bool,
+    closes_at: Option<u64>,
+    hide_results_until_close: bool,
+    // each node's ballot, as indices into options
+    ballots: HashMap<String, Vec<usize>>,
+    close_announced: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublicPoll {
+    question: String,
+    options: Vec<String>,
+    multiple_choice: bool,
+    closes_at: Option<u64>,
+    hide_results_until_close: bool,
+    is_closed: bool,
+    total_voters: usize,
+    // None while results are hidden
+    tallies: Option<Vec<u32>>,
+}
+
+impl Poll {
+    pub fn new(new_poll: NewPoll, now: u64) -> Result<Self, String> {
+        let question = new_poll.question.trim().to_string();
+        if question.is_empty() || question.chars().count() > MAX_QUESTION_LENGTH {
+            return Err(format!("poll questions must be 1 to {} characters", MAX_QUESTION_LENGTH));
+        }
+        let options: Vec<String> = new_poll.options.iter()
+            .map(|option| option.trim().to_string())
+            .collect();
+        if options.len() < MIN_POLL_OPTIONS || options.len() > MAX_POLL_OPTIONS {
+            return Err(format!("polls need {} to {} options", MIN_POLL_OPTIONS, MAX_POLL_OPTIONS));
+        }
+        if options.iter().any(|option| option.is_empty() || option.chars().count() > MAX_OPTION_LENGTH) {
+            return Err(format!("poll options must be 1 to {} characters", MAX_OPTION_LENGTH));
+        }
+        if new_poll.closes_at.is_some_and(|closes_at| closes_at <= now) {
+            return Err("poll close time is in the past".to_string());
+        }
+        if new_poll.hide_results_until_close && new_poll.closes_at.is_none() {
+            return Err("polls that hide results until they close need a close time".to_string());
+        }
+        Ok(Poll {
+            question,
+            options,
+            multiple_choice: new_poll.multiple_choice,
+            closes_at: new_poll.closes_at,
+            hide_results_until_close: new_poll.hide_results_until_close,
+            ballots: HashMap::new(),
+            close_announced: false,
+        })
+    }
+
+    pub fn searchable_text(&self) -> String {
+        format!("{} {}", self.question, self.options.join(" "))
+    }
+
+    pub fn is_closed(&self, now: u64) -> bool {
+        self.closes_at.is_some_and(|closes_at| closes_at <= now)
+    }
+
+    // Each node holds one ballot, which it may replace until the poll closes
+    pub fn vote(&mut self, node: &str, mut choices: Vec<usize>, now: u64) -> Result<(), String> {
+        if self.is_closed(now) {
+            return Err("this poll is closed".to_string());
+        }
+        choices.sort_unstable();
+        choices.dedup();
+        if choices.is_empty() || choices.iter().any(|&choice| choice >= self.options.len()) {
+            return Err("invalid poll choice".to_string());
+        }
+        if !self.multiple_choice && choices.len() > 1 {
+            return Err("this poll allows only one choice".to_string());
+        }
+        self.ballots.insert(node.to_string(), choices);
+        Ok(())
+    }
+
+    // When this poll still has to be announced as closed
+    pub fn pending_close(&self) -> Option<u64> {
+        if self.close_announced {
+            None
+        } else {
+            self.closes_at
+        }
+    }
+
+    // True exactly once, the first time it is asked after the poll closes
+    pub fn take_close_announcement(&mut self, now: u64) -> bool {
+        if self.close_announced || !self.is_closed(now) {
+            return false;
+        }
+        self.close_announced = true;
+        true
+    }
+
+    pub fn to_public(&self, now: u64) -> PublicPoll {
+        let is_closed = self.is_closed(now);
+        let tallies = if self.hide_results_until_close && !is_closed {
+            None
+        } else {
+            let mut tallies = vec![0; self.options.len()];
+            for choices in self.ballots.values() {
+                for &choice in choices {
+                    tallies[choice] += 1;
+                }
+            }
+            Some(tallies)
+        };
+        PublicPoll {
+            question: self.question.clone(),
+            options: self.options.clone(),
+            multiple_choice: self.multiple_choice,
+            closes_at: self.closes_at,
+            hide_results_until_close: self.hide_results_until_close,
+            is_closed,
+            total_voters: self.ballots.len(),
+            tallies,
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use hyperware_process_lib::{Address, Request};
+use serde::{Serialize, Deserialize};
+
+// Link metadata is fetched by the linkpreview process, which never blocks on
+// a site. The forum hands it links and collects what's finished each time
+// its loop comes around. Thumbnails aren't kept, since showing one would have
+// every subscriber load it from the linked site.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LinkPreview {
+    pub url: String,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub site_name: Option<String>,
+    pub fetched_at: u64,
+}
+
+// Requests to linkpreview
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum LinkPreviewRequest {
+    Fetch {
+        requester: String,
+        link: String,
+    },
+    Take {
+        requester: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum LinkPreviewResponse {
+    Previews(Vec<FetchedPreview>),
+}
+
+// metadata is None when the link couldn't be previewed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FetchedPreview {
+    pub link: String,
+    pub metadata: Option<PreviewMetadata>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreviewMetadata {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub site_name: Option<String>,
+}
+
+impl FetchedPreview {
+    pub fn into_preview(self, now: u64) -> Option<LinkPreview> {
+        let metadata = self.metadata?;
+        Some(LinkPreview {
+            url: self.link,
+            title: metadata.title,
+            description: metadata.description,
+            site_name: metadata.site_name,
+            fetched_at: now,
+        })
+    }
+}
+
+// linkpreview answers Take straight from memory, so this wait is short
+const TAKE_TIMEOUT: u64 = 5; // seconds
+
+fn linkpreview_address(our: &Address) -> Address {
+    Address::new(our.node(), ("linkpreview", our.package(), our.publisher()))
+}
+
+// Starts a fetch, its result comes back through take_previews
+pub fn request_preview(our: &Address, requester: &str, link: &str) -> anyhow::Result<()> {
+    let request = LinkPreviewRequest::Fetch {
+        requester: requester.to_string(),
+        link: link.to_string(),
+    };
+    Request::to(linkpreview_address(our))
+        .body(serde_json::to_vec(&request)?)
+        .send()
+}
+
+pub fn take_previews(our: &Address, requester: &str) -> anyhow::Result<Vec<FetchedPreview>> {
+    let request = LinkPreviewRequest::Take {
+        requester: requester.to_string(),
+    };
+    let response = Request::to(linkpreview_address(our))
+        .body(serde_json::to_vec(&request)?)
+        .send_and_await_response(TAKE_TIMEOUT)??;
+    let LinkPreviewResponse::Previews(previews) = serde_json::from_slice(response.body())?;
+    Ok(previews)
+}
@@ -0,0 +1,59 @@
+use std::collections::{HashMap, HashSet};
+
+const MIN_TERM_LENGTH: usize = 2;
+const MAX_TERM_LENGTH: usize = 32;
+
+// Inverted index from lowercase terms to the ids of posts that contain them.
+// It is rebuilt from the posts on load rather than persisted.
+#[derive(Debug, Clone, Default)]
+pub struct SearchIndex {
+    terms: HashMap<String, HashSet<u64>>,
+}
+
+impl SearchIndex {
+    pub fn insert(&mut self, post_id: u64, text: &str) {
+        for term in tokenize(text) {
+            self.terms.entry(term).or_default().insert(post_id);
+        }
+    }
+
+    pub fn remove(&mut self, post_id: u64, text: &str) {
+        for term in tokenize(text) {
+            if let Some(ids) = self.terms.get_mut(&term) {
+                ids.remove(&post_id);
+                if ids.is_empty() {
+                    self.terms.remove(&term);
+                }
+            }
+        }
+    }
+
+    // Post ids matching at least one of the terms, with how many distinct terms each matched
+    pub fn lookup(&self, terms: &HashSet<String>) -> HashMap<u64, usize> {
+        let mut matches: HashMap<u64, usize> = HashMap::new();
+        for term in terms {
+            if let Some(ids) = self.terms.get(term) {
+                for id in ids {
+                    *matches.entry(*id).or_default() += 1;
+                }
+            }
+        }
+        matches
+    }
+}
+
+pub fn tokenize(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() >= MIN_TERM_LENGTH)
+        .map(|word| word.to_lowercase().chars().take(MAX_TERM_LENGTH).collect())
+        .collect()
+}
+
+pub fn count_occurrences(text: &str, terms: &HashSet<String>) -> usize {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| {
+            let word: String = word.to_lowercase().chars().take(MAX_TERM_LENGTH).collect();
+            terms.contains(&word)
+        })
+        .count()
+}
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+
+use serde::{Serialize, Deserialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FilterAction {
+    Reject,
+    Hold,
+}
+
+// A word matches whole words case-insensitively, a regex is used as given
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContentFilter {
+    pub pattern: String,
+    pub is_regex: bool,
+    pub action: FilterAction,
+}
+
+impl ContentFilter {
+    fn to_regex(&self) -> Result<regex::Regex, regex::Error> {
+        let pattern = if self.is_regex {
+            self.pattern.clone()
+        } else {
+            format!(r"\b{}\b", regex::escape(&self.pattern))
+        };
+        regex::RegexBuilder::new(&pattern)
+            .case_insensitive(true)
+            .size_limit(1 << 16)
+            .build()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpamSettings {
+    pub max_posts_per_window: usize,
+    pub rate_window: u64, // seconds
+    pub max_text_length: usize,
+    pub max_link_length: usize,
+    pub max_image_url_length: usize,
+    pub min_account_age: u64, // seconds since the node first subscribed
+    pub filters: Vec<ContentFilter>,
+    // built from filters by compile(), so posts don't rebuild every regex
+    #[serde(skip)]
+    compiled: Vec<(regex::Regex, FilterAction)>,
+}
+
+impl Default for SpamSettings {
+    fn default() -> Self {
+        SpamSettings {
+            max_posts_per_window: 5,
+            rate_window: 60,
+            max_text_length: 10_000,
+            max_link_length: 2048,
+            max_image_url_length: 2048,
+            min_account_age: 0,
+            filters: Vec::new(),
+            compiled: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Accept,
+    Hold,
+}
+
+impl SpamSettings {
+    // Must run after the settings are received or loaded. Filters that don't
+    // compile are left out, and the first such error is returned so new
+    // settings can be turned away.
+    pub fn compile(&mut self) -> Result<(), String> {
+        let mut compiled = Vec::new();
+        let mut error = None;
+        for filter in &self.filters {
+            match filter.to_regex() {
+                Ok(re) => compiled.push((re, filter.action)),
+                Err(e) => {
+                    error.get_or_insert_with(|| format!("invalid filter {:?}: {}", filter.pattern, e));
+                }
+            }
+        }
+        self.compiled = compiled;
+        error.map_or(Ok(()), Err)
+    }
+
+    pub fn check_content(&self, text: &str, link: Option<&str>, image_url: Option<&str>) -> Result<Verdict, String> {
+        if text.chars().count() > self.max_text_length {
+            return Err(format!("posts can be at most {} characters", self.max_text_length));
+        }
+        if link.is_some_and(|link| link.len() > self.max_link_length) {
+            return Err(format!("links can be at most {} characters", self.max_link_length));
+        }
+        if image_url.is_some_and(|url| url.len() > self.max_image_url_length) {
+            return Err(format!("image urls can be at most {} characters", self.max_image_url_length));
+        }
+
+        let mut verdict = Verdict::Accept;
+        for (re, action) in &self.compiled {
+            let matched = re.is_match(text)
+                || link.is_some_and(|link| re.is_match(link))
+                || image_url.is_some_and(|url| re.is_match(url));
+            if !matched {
+                continue;
+            }
+            match action {
+                FilterAction::Reject => return Err("post was rejected by a content filter".to_string()),
+                FilterAction::Hold => verdict = Verdict::Hold,
+            }
+        }
+        Ok(verdict)
+    }
+}
+
+// Recent post times per node. Not persisted, a restart just forgets them.
+#[derive(Debug, Clone, Default)]
+pub struct RateLimiter {
+    recent: HashMap<String, Vec<u64>>,
+}
+
+impl RateLimiter {
+    // Records the attempt if the node is still under its limit
+    pub fn try_acquire(&mut self, node: &str, now: u64, settings: &SpamSettings) -> bool {
+        let window_start = now.saturating_sub(settings.rate_window);
+        let times = self.recent.entry(node.to_string()).or_default();
+        times.retain(|&time| time > window_start);
+        if times.len() >= settings.max_posts_per_window {
+            return false;
+        }
+        times.push(now);
+        true
+    }
+}
@@ -0,0 +1,404 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hyperware_process_lib::{await_message, call_init, get_blob, println, Address, Message, Request, Response, SendError};
+use hyperware_process_lib::http::client::{HttpClientAction, OutgoingHttpRequest};
+use serde::{Serialize, Deserialize};
+use url::Url;
+
+wit_bindgen::generate!({
+    path: "target/wit",
+    world: "process-v1",
+});
+
+// Fetches link metadata for the forum. dartfrog_lib's message loop can't
+// hand Responses back to a service, so the forum would have to block on every
+// fetch. This process only ever sends requests without waiting, keeps each
+// fetch's progress keyed by the request context, and holds finished previews
+// until the forum collects them. Only forum may use it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum LinkPreviewRequest {
+    // starts a fetch, answered later through Take
+    Fetch {
+        requester: String,
+        link: String,
+    },
+    // answered right away with LinkPreviewResponse
+    Take {
+        requester: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum LinkPreviewResponse {
+    Previews(Vec<FetchedPreview>),
+}
+
+// metadata is None when the link couldn't be previewed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FetchedPreview {
+    pub link: String,
+    pub metadata: Option<PreviewMetadata>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreviewMetadata {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub site_name: Option<String>,
+}
+
+// What http-client answers a request with, the body comes in the blob
+#[derive(Debug, Deserialize)]
+enum HttpClientResponse {
+    Http(HttpStatus),
+}
+
+#[derive(Debug, Deserialize)]
+struct HttpStatus {
+    status: u16,
+    headers: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OEmbed {
+    title: Option<String>,
+    author_name: Option<String>,
+    provider_name: Option<String>,
+}
+
+const FETCH_TIMEOUT: u64 = 5; // seconds
+const MAX_FETCH_BYTES: usize = 512 * 1024;
+const MAX_REDIRECTS: u8 = 5;
+const MAX_TITLE_LENGTH: usize = 200;
+const MAX_DESCRIPTION_LENGTH: usize = 500;
+const MAX_IN_FLIGHT: usize = 16;
+// previews nobody collected are dropped after this long
+const RESULT_EXPIRY: u64 = 10 * 60; // seconds
+const MAX_RESULTS_PER_REQUESTER: usize = 256;
+
+#[derive(Debug, Clone, Copy)]
+enum Stage {
+    Page,
+    OEmbed,
+}
+
+#[derive(Debug, Clone)]
+struct Fetch {
+    requester: String,
+    link: String,
+    // where the current request went, after any redirects
+    url: Url,
+    stage: Stage,
+    redirects: u8,
+    // what the page itself gave, while its oEmbed endpoint is asked for the rest
+    metadata: Option<PreviewMetadata>,
+}
+
+#[derive(Debug, Default)]
+struct LinkPreviewState {
+    next_id: u64,
+    fetches: HashMap<u64, Fetch>,
+    results: HashMap<String, Vec<(u64, FetchedPreview)>>,
+}
+
+impl LinkPreviewState {
+    fn start(&mut self, requester: String, link: String) {
+        let url = public_http_url(&link).filter(|_| self.fetches.len() < MAX_IN_FLIGHT);
+        let Some(url) = url else {
+            return self.finish(requester, link, None);
+        };
+        let id = self.next_id;
+        self.next_id += 1;
+        let fetch = Fetch { requester, link, url, stage: Stage::Page, redirects: 0, metadata: None };
+        self.send(id, fetch);
+    }
+
+    fn send(&mut self, id: u64, fetch: Fetch) {
+        match send_get(id, &fetch.url) {
+            Ok(()) => {
+                self.fetches.insert(id, fetch);
+            }
+            Err(e) => {
+                println!("linkpreview error sending request: {:?}", e);
+                self.finish_stage(fetch);
+            }
+        }
+    }
+
+    fn handle_response(&mut self, id: u64, body: &[u8]) {
+        let Some(mut fetch) = self.fetches.remove(&id) else {
+            return;
+        };
+        let Ok(Ok(HttpClientResponse::Http(response))) = serde_json::from_slice::<Result<HttpClientResponse, serde_json::Value>>(body) else {
+            return self.finish_stage(fetch);
+        };
+        // http-client may hand a redirect back instead of following it, and
+        // each hop has to pass the same check as the original link
+        if (300..400).contains(&response.status) {
+            let target = header(&response.headers, "location")
+                .and_then(|location| fetch.url.join(location).ok())
+                .and_then(|target| public_http_url(target.as_str()));
+            let Some(target) = target.filter(|_| fetch.redirects < MAX_REDIRECTS) else {
+                return self.finish_stage(fetch);
+            };
+            fetch.redirects += 1;
+            fetch.url = target;
+            return self.send(id, fetch);
+        }
+        if !(200..300).contains(&response.status) {
+            return self.finish_stage(fetch);
+        }
+        let bytes = get_blob().map(|blob| blob.bytes).unwrap_or_default();
+        let text = String::from_utf8_lossy(&bytes[..bytes.len().min(MAX_FETCH_BYTES)]).into_owned();
+        match fetch.stage {
+            Stage::Page => {
+                let metadata = page_metadata(&text);
+                let oembed_url = oembed_link(&text)
+                    .and_then(|href| fetch.url.join(&href).ok())
+                    .and_then(|oembed_url| public_http_url(oembed_url.as_str()));
+                match oembed_url {
+                    Some(oembed_url) if metadata.title.is_none() => {
+                        fetch.metadata = Some(metadata);
+                        fetch.stage = Stage::OEmbed;
+                        fetch.url = oembed_url;
+                        fetch.redirects = 0;
+                        self.send(id, fetch);
+                    }
+                    _ => {
+                        fetch.metadata = Some(metadata);
+                        self.finish_stage(fetch);
+                    }
+                }
+            }
+            Stage::OEmbed => {
+                if let (Ok(oembed), Some(metadata)) = (serde_json::from_str::<OEmbed>(&text), fetch.metadata.as_mut()) {
+                    metadata.title = metadata.title.take().or(oembed.title);
+                    metadata.site_name = metadata.site_name.take().or(oembed.provider_name);
+                    metadata.description = metadata.description.take().or(oembed.author_name);
+                }
+                self.finish_stage(fetch);
+            }
+        }
+    }
+
+    fn handle_error(&mut self, id: u64) {
+        if let Some(fetch) = self.fetches.remove(&id) {
+            self.finish_stage(fetch);
+        }
+    }
+
+    // A failed oEmbed lookup still leaves whatever the page had
+    fn finish_stage(&mut self, mut fetch: Fetch) {
+        let metadata = fetch.metadata.take().map(limit_metadata)
+            .filter(|metadata| metadata.title.is_some() || metadata.description.is_some());
+        self.finish(fetch.requester, fetch.link, metadata);
+    }
+
+    fn finish(&mut self, requester: String, link: String, metadata: Option<PreviewMetadata>) {
+        let now = now();
+        let results = self.results.entry(requester).or_default();
+        results.retain(|(finished_at, _)| now.saturating_sub(*finished_at) < RESULT_EXPIRY);
+        if results.len() >= MAX_RESULTS_PER_REQUESTER {
+            results.remove(0);
+        }
+        results.push((now, FetchedPreview { link, metadata }));
+    }
+
+    fn take(&mut self, requester: &str) -> Vec<FetchedPreview> {
+        self.results.remove(requester)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(_, preview)| preview)
+            .collect()
+    }
+}
+
+fn header<'a>(headers: &'a HashMap<String, String>, name: &str) -> Option<&'a str> {
+    headers.iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+fn send_get(id: u64, url: &Url) -> anyhow::Result<()> {
+    let headers = HashMap::from([("Range".to_string(), format!("bytes=0-{}", MAX_FETCH_BYTES - 1))]);
+    let action = HttpClientAction::Http(OutgoingHttpRequest {
+        method: "GET".to_string(),
+        version: None,
+        url: url.to_string(),
+        headers,
+    });
+    Request::to(("our", "http-client", "distro", "sys"))
+        .body(serde_json::to_vec(&action)?)
+        .context(id.to_le_bytes().to_vec())
+        .expects_response(FETCH_TIMEOUT)
+        .send()
+}
+
+fn fetch_id(context: Option<&[u8]>) -> Option<u64> {
+    Some(u64::from_le_bytes(context?.try_into().ok()?))
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+// OpenGraph metadata, falling back to the page title and description
+fn page_metadata(html: &str) -> PreviewMetadata {
+    PreviewMetadata {
+        title: meta_content(html, "og:title").or_else(|| title_tag(html)),
+        description: meta_content(html, "og:description").or_else(|| meta_content(html, "description")),
+        site_name: meta_content(html, "og:site_name"),
+    }
+}
+
+fn limit_metadata(metadata: PreviewMetadata) -> PreviewMetadata {
+    PreviewMetadata {
+        title: metadata.title.map(|title| truncate(&title, MAX_TITLE_LENGTH)),
+        description: metadata.description.map(|description| truncate(&description, MAX_DESCRIPTION_LENGTH)),
+        site_name: metadata.site_name.map(|site_name| truncate(&site_name, MAX_TITLE_LENGTH)),
+    }
+}
+
+// Only plain http(s) urls on the default ports that don't point back into the
+// host's own network. Hostnames aren't resolved here, so names that only mean
+// something on a local network are refused outright. A public name that
+// resolves to a private address can't be caught at this layer.
+fn public_http_url(link: &str) -> Option<Url> {
+    let url = Url::parse(link).ok()?;
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return None;
+    }
+    if !url.username().is_empty() || url.password().is_some() || url.port().is_some_and(|port| port != 80 && port != 443) {
+        return None;
+    }
+    let host = url.host_str()?.trim_start_matches('[').trim_end_matches(']').trim_end_matches('.');
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return (!is_private(ip)).then_some(url);
+    }
+    let host = host.to_ascii_lowercase();
+    let local_suffixes = [".localhost", ".local", ".internal", ".lan", ".home", ".home.arpa", ".intranet", ".corp"];
+    if host == "localhost" || !host.contains('.') || local_suffixes.iter().any(|suffix| host.ends_with(suffix)) {
+        return None;
+    }
+    Some(url)
+}
+
+fn is_private(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                // carrier-grade nat, 100.64.0.0/10
+                || (ip.octets()[0] == 100 && (ip.octets()[1] & 0xc0) == 64)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_private(IpAddr::V4(ip)),
+            None => {
+                ip.is_loopback()
+                    || ip.is_unspecified()
+                    // unique local, fc00::/7
+                    || (ip.segments()[0] & 0xfe00) == 0xfc00
+                    // link local, fe80::/10
+                    || (ip.segments()[0] & 0xffc0) == 0xfe80
+            }
+        },
+    }
+}
+
+// Finds <meta property="name" content="..."> or <meta name="name" ...>, in either attribute order
+fn meta_content(html: &str, name: &str) -> Option<String> {
+    let name = regex::escape(name);
+    let patterns = [
+        format!(r#"(?is)<meta[^>]+(?:property|name)\s*=\s*["']{}["'][^>]*?content\s*=\s*["']([^"']*)["']"#, name),
+        format!(r#"(?is)<meta[^>]+content\s*=\s*["']([^"']*)["'][^>]*?(?:property|name)\s*=\s*["']{}["']"#, name),
+    ];
+    patterns.iter()
+        .filter_map(|pattern| regex::Regex::new(pattern).ok())
+        .find_map(|re| re.captures(html).map(|caps| decode_entities(caps[1].trim())))
+        .filter(|content| !content.is_empty())
+}
+
+fn title_tag(html: &str) -> Option<String> {
+    let re = regex::Regex::new(r"(?is)<title[^>]*>(.*?)</title>").ok()?;
+    re.captures(html)
+        .map(|caps| decode_entities(caps[1].trim()))
+        .filter(|title| !title.is_empty())
+}
+
+fn oembed_link(html: &str) -> Option<String> {
+    let re = regex::Regex::new(r#"(?is)<link[^>]+type\s*=\s*["']application/json\+oembed["'][^>]*>"#).ok()?;
+    let tag = re.find(html)?.as_str();
+    let href = regex::Regex::new(r#"(?is)href\s*=\s*["']([^"']*)["']"#).ok()?;
+    href.captures(tag).map(|caps| decode_entities(&caps[1]))
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&apos;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+fn truncate(text: &str, max: usize) -> String {
+    text.chars().take(max).collect()
+}
+
+fn handle_message(our: &Address, state: &mut LinkPreviewState) -> anyhow::Result<()> {
+    let message = match await_message() {
+        Ok(message) => message,
+        // timeouts and unreachable sites come back with the fetch's context
+        Err(SendError { context, .. }) => {
+            if let Some(id) = fetch_id(context.as_deref()) {
+                state.handle_error(id);
+            }
+            return Ok(());
+        }
+    };
+    match message {
+        Message::Response { ref source, ref body, ref context, .. } => {
+            if source.node == our.node && source.process.to_string() == "http-client:distro:sys" {
+                if let Some(id) = fetch_id(context.as_deref()) {
+                    state.handle_response(id, body);
+                }
+            }
+            Ok(())
+        }
+        Message::Request { ref source, ref body, .. } => {
+            let is_forum = source.node == our.node
+                && source.process.process() == "forum"
+                && source.package_id() == our.package_id();
+            if !is_forum {
+                return Ok(());
+            }
+            match serde_json::from_slice::<LinkPreviewRequest>(body)? {
+                LinkPreviewRequest::Fetch { requester, link } => state.start(requester, link),
+                LinkPreviewRequest::Take { requester } => {
+                    let previews = LinkPreviewResponse::Previews(state.take(&requester));
+                    Response::new()
+                        .body(serde_json::to_vec(&previews)?)
+                        .send()?;
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+call_init!(init);
+fn init(our: Address) {
+    println!("init linkpreview");
+    let mut state = LinkPreviewState::default();
+    loop {
+        if let Err(e) = handle_message(&our, &mut state) {
+            println!("linkpreview error handling message: {:?}", e);
+        }
+    }
+}
@@ -21,12 +21,10 @@ function App() {
 
 function ServiceRoute() {
   const { id } = useParams();
-  const { page, setPage } = usePageStore();
+  const { handleUpdate } = usePageStore();
 
   const onServiceMessage = (msg) => {
-    if (msg.Page) {
-      setPage(msg.Page.Page);
-    }
+    handleUpdate(msg);
   };
 
   return (
@@ -1,4 +1,4 @@
-import React, { useEffect, useState, useCallback } from 'react';
+import React, { useEffect, useState, useCallback, useRef } from 'react';
 import { ServiceID } from '@dartfrog/puddle';
 import usePageStore from '../store/page';
 import {useServiceStore} from '@dartfrog/puddle';
@@ -7,14 +7,107 @@ import {useServiceStore} from '@dartfrog/puddle';
 interface PagePluginBoxProps {
 }
 
+// Resolves a link in a page the way the public site would, where every page
+// sits under the site directory. Returns null for links that leave the site.
+const resolvePageLink = (href: string, currentPath: string): string | null => {
+  if (href.startsWith('#') || /^[a-z][a-z0-9+.-]*:/i.test(href) || href.startsWith('//')) {
+    return null;
+  }
+  const base = new URL(`http://site/${currentPath}`);
+  const resolved = new URL(href, base);
+  return decodeURIComponent(resolved.pathname).replace(/^\/+|\/+$/g, '');
+};
+
+const ASSET_PREFIX = "assets/";
+const ASSET_ELEMENTS = 'img[src], source[src], link[rel="stylesheet"][href]';
+
 const PagePluginBox: React.FC = ({ }) => {
   const [isAuthor, setIsAuthor] = useState(false);
   const [editMode, setEditMode] = useState(false);
-  const {page, sendPageEdit} = usePageStore();
+  const [showAssets, setShowAssets] = useState(false);
+  const {index, paths, path, page, publicUrls, notFound, assets, assetData, assetRejection, requestPage, sendPageEdit, createPage, renamePage, deletePage, setIndex, requestAssets, uploadAsset, deleteAsset, requestAsset} = usePageStore();
   const [editableText, setEditableText] = useState(page);
 
   const {api, serviceId} = useServiceStore();
 
+  useEffect(() => {
+    // the page being viewed was deleted
+    if (api && path === null && index) {
+      requestPage(api, index);
+    }
+  }, [api, path, index]);
+
+  const handleCreate = useCallback(() => {
+    const newPath = window.prompt("path of the new page, e.g. about or blog/first-post");
+    if (newPath) {
+      createPage(api, newPath, "");
+      requestPage(api, newPath);
+    }
+  }, [api]);
+
+  const handleRename = useCallback(() => {
+    const newPath = window.prompt("new path", path ?? "");
+    if (newPath && newPath !== path) {
+      renamePage(api, path, newPath);
+    }
+  }, [api, path]);
+
+  const publicUrl = path ? publicUrls[path] : undefined;
+
+  const navigation = (
+    <div style={{ display: 'flex', gap: '4px', alignItems: 'center', flexWrap: 'wrap' }}>
+      <select
+        value={path ?? ""}
+        onChange={(e) => requestPage(api, e.target.value)}
+      >
+        {paths.map((p) => (
+          <option key={p} value={p}>{p === index ? `${p} (index)` : p}</option>
+        ))}
+      </select>
+      {notFound && <span>no page at {notFound}</span>}
+      {publicUrl && (
+        <a href={publicUrl.url} target="_blank" rel="noreferrer">public link</a>
+      )}
+      {isAuthor && (
+        <>
+          <button onClick={handleCreate}>New page</button>
+          <button onClick={handleRename} disabled={!path}>Rename</button>
+          <button onClick={() => setIndex(api, path)} disabled={!path || path === index}>Make index</button>
+          <button onClick={() => deletePage(api, path)} disabled={!path || path === index}>Delete</button>
+          <button onClick={() => setShowAssets(!showAssets)}>{showAssets ? "Hide assets" : "Assets"}</button>
+        </>
+      )}
+    </div>
+  );
+
+  useEffect(() => {
+    if (api && showAssets) {
+      requestAssets(api);
+    }
+  }, [api, showAssets]);
+
+  const assetsPanel = showAssets && (
+    <div style={{ display: 'flex', flexDirection: 'column', gap: '2px', maxHeight: '30%', overflowY: 'auto' }}>
+      <input
+        type="file"
+        onChange={(e) => {
+          const file = e.target.files?.[0];
+          if (file) uploadAsset(api, file);
+          e.target.value = "";
+        }}
+      />
+      {assetRejection && <span style={{ color: '#ff6b6b' }}>{assetRejection}</span>}
+      {assets.length === 0 && <span>no assets yet</span>}
+      {assets.map((asset) => (
+        <div key={asset.name} style={{ display: 'flex', gap: '4px', alignItems: 'center' }}>
+          <code style={{ flexGrow: 1 }}>{ASSET_PREFIX}{asset.name}</code>
+          <span>{(asset.size / 1024).toFixed(1)} KB</span>
+          <button onClick={() => deleteAsset(api, asset.name)}>Delete</button>
+        </div>
+      ))}
+    </div>
+  );
+
   useEffect(() => {
     const parsedServiceId = ServiceID.fromString(serviceId);
     if (!parsedServiceId) return;
@@ -27,12 +120,63 @@ const PagePluginBox: React.FC = ({ }) => {
   }, [page]);
 
   const handleSave = useCallback(() => {
-    sendPageEdit(api, editableText);
+    sendPageEdit(api, path, editableText);
     setEditMode(false);  // Exit edit mode after save
-  }, [editableText, api]);
+  }, [editableText, path, api]);
+
+  // Page scripts stay disabled, but allow-same-origin lets us reach into the
+  // frame so links between pages load the page instead of a dead url
+  const iframeRef = useRef<HTMLIFrameElement>(null);
+  const requestedAssets = useRef<Set<string>>(new Set());
+
+  // Relative asset urls don't resolve from a srcdoc frame, and private sites
+  // aren't served at all, so swap in the asset data fetched from the service
+  const applyAssets = useCallback((doc: Document) => {
+    doc.querySelectorAll<HTMLElement>(ASSET_ELEMENTS).forEach((el) => {
+      const attr = el.hasAttribute('src') ? 'src' : 'href';
+      const original = el.dataset.pageAsset ?? el.getAttribute(attr) ?? "";
+      const target = resolvePageLink(original, path ?? index ?? "");
+      if (!target?.startsWith(ASSET_PREFIX)) return;
+      const name = target.slice(ASSET_PREFIX.length);
+      el.dataset.pageAsset = original;
+      if (assetData[name]) {
+        el.setAttribute(attr, assetData[name]);
+      } else if (!requestedAssets.current.has(name)) {
+        requestedAssets.current.add(name);
+        requestAsset(api, name);
+      }
+    });
+  }, [api, path, index, assetData]);
+
+  useEffect(() => {
+    // uploads replace the cached data, so fetch them again when next used
+    requestedAssets.current = new Set(Object.keys(assetData));
+    const doc = iframeRef.current?.contentDocument;
+    if (doc) applyAssets(doc);
+  }, [assetData]);
+
+  const handleFrameLoad = useCallback(() => {
+    const doc = iframeRef.current?.contentDocument;
+    if (!doc) return;
+    applyAssets(doc);
+    doc.addEventListener('click', (event) => {
+      const anchor = (event.target as Element | null)?.closest?.('a');
+      const href = anchor?.getAttribute('href');
+      if (!href || href.startsWith('#')) return;
+      event.preventDefault();
+      const target = resolvePageLink(href, path ?? index ?? "");
+      if (target === null) {
+        window.open(href, '_blank', 'noopener,noreferrer');
+      } else if (target) {
+        requestPage(api, target);
+      }
+    });
+  }, [api, path, index, applyAssets]);
 
   const iframeView = (
     <iframe
+      ref={iframeRef}
+      onLoad={handleFrameLoad}
       srcDoc={page}
       style={{
         width: '100%',
@@ -41,7 +185,7 @@ const PagePluginBox: React.FC = ({ }) => {
         border: 'none',
         boxSizing: 'border-box',
       }}
-      sandbox=""
+      sandbox="allow-same-origin"
     />
   );
 
@@ -88,6 +232,8 @@ const PagePluginBox: React.FC = ({ }) => {
               overflow: 'hidden',
             }}
             >
+            {navigation}
+            {assetsPanel}
             {iframeView}
             <button onClick={() => setEditMode(true)}>Edit</button>
           </div>
@@ -108,6 +254,7 @@ const PagePluginBox: React.FC = ({ }) => {
         // border: '1px solid red',
       }}
     >
+      {navigation}
       {iframeView}
     </div>
   )
@@ -6,32 +6,212 @@ export const PLUGIN_NAME = "page:dartfrog:gliderlabs.os";
 
 
 
+export interface PublicUrl {
+  url: string,
+  revision: number,
+}
+
+export interface PageAsset {
+  name: string,
+  mime: string,
+  size: number,
+  url: string,
+  uploaded_at: number,
+}
+
 export interface PageStore {
+  index: string | null,
+  paths: string[],
+  path: string | null,
   page: string | null,
-  setPage: (page: string) => void
+  // the path asked for with requestPage, shown as soon as it arrives
+  requested: string | null,
+  // only known for public services
+  publicUrls: Record<string, PublicUrl>,
+  notFound: string | null,
+  // only listed for the host
+  assets: PageAsset[],
+  // data urls for assets fetched through the service, by name
+  assetData: Record<string, string>,
+  // why the last upload was turned down
+  assetRejection: string | null,
+  setPage: (path: string, page: string) => void
   //
-  sendPageEdit: (api:ServiceApi, text: string) => void
-  // 
-  get: () => PageStore 
+  requestPage: (api: ServiceApi, path: string) => void
+  sendPageEdit: (api:ServiceApi, path: string, text: string) => void
+  createPage: (api: ServiceApi, path: string, text: string) => void
+  renamePage: (api: ServiceApi, from: string, to: string) => void
+  deletePage: (api: ServiceApi, path: string) => void
+  setIndex: (api: ServiceApi, path: string) => void
+  requestAssets: (api: ServiceApi) => void
+  uploadAsset: (api: ServiceApi, file: File) => void
+  deleteAsset: (api: ServiceApi, name: string) => void
+  requestAsset: (api: ServiceApi, name: string) => void
+  handleUpdate: (update: any) => void
+  //
+  get: () => PageStore
   set: (partial: PageStore | Partial<PageStore>) => void
 }
 
 const usePageStore = create<PageStore>((set, get) => ({
-  // 
+  //
+  index: null,
+  paths: [],
+  path: null,
   page: null,
-  setPage: (page) => set({ page }),
-  // 
-  sendPageEdit: (api, text) => {
-    let req = 
+  requested: null,
+  publicUrls: {},
+  notFound: null,
+  assets: [],
+  assetData: {},
+  assetRejection: null,
+  setPage: (path, page) => set({ path, page, notFound: null }),
+  //
+  requestPage: (api, path) => {
+    set({ requested: path });
+    let req = {
+      "Page": {
+        "RequestPage": path
+      }
+    }
+    api.sendToService(req);
+  },
+  sendPageEdit: (api, path, text) => {
+    let req =
       {
       "Page": {
-        "EditPage": 
-          text
+        "EditPage": {
+          path,
+          page: text
+        }
+      }
+    }
+    api.sendToService(req);
+  },
+  createPage: (api, path, text) => {
+    let req = {
+      "Page": {
+        "CreatePage": {
+          path,
+          page: text
+        }
+      }
+    }
+    api.sendToService(req);
+  },
+  renamePage: (api, from, to) => {
+    let req = {
+      "Page": {
+        "RenamePage": { from, to }
+      }
+    }
+    api.sendToService(req);
+  },
+  deletePage: (api, path) => {
+    let req = {
+      "Page": {
+        "DeletePage": path
       }
     }
     api.sendToService(req);
   },
-  // 
+  setIndex: (api, path) => {
+    let req = {
+      "Page": {
+        "SetIndex": path
+      }
+    }
+    api.sendToService(req);
+  },
+  requestAssets: (api) => {
+    let req = {
+      "Page": "ListAssets"
+    }
+    api.sendToService(req);
+  },
+  uploadAsset: (api, file) => {
+    set({ assetRejection: null });
+    const reader = new FileReader();
+    reader.onload = () => {
+      // drop the "data:<mime>;base64," prefix
+      const data = (reader.result as string).split(',')[1] ?? "";
+      let req = {
+        "Page": {
+          "UploadAsset": {
+            name: file.name,
+            mime: file.type,
+            data,
+          }
+        }
+      }
+      api.sendToService(req);
+    };
+    reader.readAsDataURL(file);
+  },
+  deleteAsset: (api, name) => {
+    let req = {
+      "Page": {
+        "DeleteAsset": name
+      }
+    }
+    api.sendToService(req);
+  },
+  requestAsset: (api, name) => {
+    let req = {
+      "Page": {
+        "RequestAsset": name
+      }
+    }
+    api.sendToService(req);
+  },
+  handleUpdate: (update) => {
+    if (!update.Page) return;
+    const upd = update.Page;
+    if (upd.Site) {
+      set({ index: upd.Site.index, paths: upd.Site.paths });
+    } else if (upd.Page) {
+      // pages other than the one being viewed or asked for are pushed on every edit, ignore those
+      const { path: current, requested } = get();
+      if (requested === upd.Page.path) {
+        set({ requested: null });
+        get().setPage(upd.Page.path, upd.Page.page);
+      } else if (current === null || current === upd.Page.path) {
+        get().setPage(upd.Page.path, upd.Page.page);
+      }
+    } else if (upd.PublicUrl) {
+      const { path, url, revision } = upd.PublicUrl;
+      set({ publicUrls: { ...get().publicUrls, [path]: { url, revision } } });
+    } else if (upd.RenamedPage) {
+      if (get().path === upd.RenamedPage.from) {
+        set({ path: upd.RenamedPage.to });
+      }
+    } else if (upd.DeletedPage) {
+      if (get().path === upd.DeletedPage) {
+        set({ path: null, page: null });
+      }
+    } else if (upd.PageNotFound !== undefined) {
+      if (get().requested === upd.PageNotFound) {
+        set({ requested: null });
+      }
+      set({ notFound: upd.PageNotFound });
+    } else if (upd.Assets) {
+      set({ assets: upd.Assets });
+    } else if (upd.NewAsset) {
+      const { [upd.NewAsset.name]: _, ...assetData } = get().assetData;
+      const assets = get().assets.filter((a) => a.name !== upd.NewAsset.name);
+      set({ assets: [...assets, upd.NewAsset].sort((a, b) => a.name.localeCompare(b.name)), assetData });
+    } else if (upd.DeletedAsset) {
+      const { [upd.DeletedAsset]: _, ...assetData } = get().assetData;
+      set({ assets: get().assets.filter((a) => a.name !== upd.DeletedAsset), assetData });
+    } else if (upd.AssetRejected) {
+      const { name, reason } = upd.AssetRejected;
+      set({ assetRejection: `${name}: ${reason}` });
+    } else if (upd.AssetData) {
+      const { name, mime, data } = upd.AssetData;
+      set({ assetData: { ...get().assetData, [name]: `data:${mime};base64,${data}` } });
+    }
+  },
+  //
   get,
   set,
 }))
@@ -1,9 +1,15 @@
+use std::collections::HashMap;
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use dartfrog_lib::*;
-use hyperware_process_lib::{call_init, http::server, Address};
+use hyperware_process_lib::{call_init, http::server, vfs, Address, Request};
 use serde::{Serialize, Deserialize};
 use constants::DEFAULT_PAGE;
+use timers::handle_timers;
 
 mod constants;
+#[path = "../../shared/timers.rs"]
+mod timers;
 
 wit_bindgen::generate!({
     path: "target/wit",
@@ -33,12 +39,14 @@ pub enum AppRequest {
 #[derive(Debug, Clone)]
 pub struct AppState {
     pub provider: AppProviderState,
+    pub next_timer: u64,
 }
 
 impl AppState {
     pub fn new(our: &Address) -> Self {
         AppState {
             provider: AppProviderState::new(our),
+            next_timer: 0,
         }
     }
 }
@@ -51,7 +59,12 @@ impl AppServiceState for AppService {
         }
     }
     fn init(&mut self, our: &Address, service: &Service) -> anyhow::Result<()> {
-        default_load_service::<Self>(our, &service.id.to_string(), self)
+        default_load_service::<Self>(our, &service.id.to_string(), self)?;
+        self.page.upgrade();
+        // whatever pagehost still serves may be stale, so always republish
+        self.page.published = None;
+        self.page.sync_publication(our, service)?;
+        self.save_if_dirty(our, service)
     }
 
     fn save(&mut self, our: &Address, service: &Service) -> anyhow::Result<()> {
@@ -59,65 +72,671 @@ impl AppServiceState for AppService {
     }
 
     fn handle_subscribe(&mut self, subscriber_node: String, our: &Address, service: &Service) -> anyhow::Result<()> {
+        self.page.sync_publication(our, service)?;
         self.page.handle_subscribe(subscriber_node.clone(), our, service)?;
         self.chat.handle_subscribe(subscriber_node, our, service)?;
-        Ok(())
+        self.save_if_dirty(our, service)
     }
 
     fn handle_request(&mut self, from: String, req: String, our: &Address, service: &Service) -> anyhow::Result<()> {
         let request = serde_json::from_str::<AppRequest>(&req)?;
-        match request {
+        self.page.sync_publication(our, service)?;
+        let result = match request {
             AppRequest::Page(page_request) => {
                 self.page.handle_request(from, page_request, our, service)
             }
             AppRequest::Chat(chat_request) => {
+                // chat keeps its own state and can't report changes, so assume it changed
+                self.page.dirty = true;
                 self.chat.handle_request(from, chat_request, our, service)
             }
+        };
+        self.save_if_dirty(our, service)?;
+        result
+    }
+}
+
+impl AppService {
+    // The service's access can change without a request, so this runs on a timer too
+    fn handle_timer(&mut self, our: &Address, service: &Service) -> anyhow::Result<()> {
+        let result = self.page.sync_publication(our, service);
+        self.save_if_dirty(our, service)?;
+        result
+    }
+
+    // Runs even when a request failed partway, so whatever it already changed is kept
+    fn save_if_dirty(&mut self, our: &Address, service: &Service) -> anyhow::Result<()> {
+        if self.page.take_dirty() {
+            self.save(our, service)?;
         }
+        Ok(())
     }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum PageUpdate {
-    Page(String),
+    Site {
+        index: String,
+        paths: Vec<String>,
+    },
+    Page {
+        path: String,
+        page: String,
+    },
+    PublicUrl {
+        path: String,
+        url: String,
+        revision: u64,
+    },
+    RenamedPage {
+        from: String,
+        to: String,
+    },
+    DeletedPage(String),
+    PageNotFound(String),
+    Assets(Vec<PageAsset>),
+    NewAsset(PageAsset),
+    DeletedAsset(String),
+    AssetRejected {
+        name: String,
+        reason: String,
+    },
+    // base64 contents, for subscribers of services pagehost doesn't serve
+    AssetData {
+        name: String,
+        mime: String,
+        data: String,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum PageRequest {
-    EditPage(String),
+    RequestPage(String),
+    EditPage {
+        path: String,
+        page: String,
+    },
+    CreatePage {
+        path: String,
+        page: String,
+    },
+    RenamePage {
+        from: String,
+        to: String,
+    },
+    DeletePage(String),
+    SetIndex(String),
+    UploadAsset {
+        name: String,
+        mime: String,
+        // base64
+        data: String,
+    },
+    DeleteAsset(String),
+    ListAssets,
+    RequestAsset(String),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct PageServiceState {
+pub struct PageRevision {
+    pub revision: u64,
+    pub time: u64,
+    pub page: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SitePage {
     pub page: String,
+    pub revisions: Vec<PageRevision>,
+}
+
+impl SitePage {
+    fn new(page: String) -> Self {
+        SitePage {
+            page,
+            revisions: vec![],
+        }
+    }
+
+    fn current_revision(&self) -> u64 {
+        self.revisions.last().map(|r| r.revision).unwrap_or(0)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PageAsset {
+    pub name: String,
+    pub mime: String,
+    pub size: u64,
+    pub url: String,
+    pub uploaded_at: u64,
+}
+
+const DEFAULT_INDEX: &str = "index";
+const MAX_PAGE_PATH_LENGTH: usize = 128;
+// older revisions are dropped and no longer served
+const MAX_REVISIONS_PER_PAGE: usize = 20;
+
+const ASSETS_DRIVE: &str = "assets";
+const ASSETS_URL_SEGMENT: &str = "assets";
+const MAX_ASSET_NAME_LENGTH: usize = 64;
+const MAX_ASSET_SIZE: usize = 2 * 1024 * 1024; // 2 MB
+const MAX_ASSETS: usize = 256;
+const ALLOWED_ASSET_MIMES: [&str; 7] = [
+    "image/png",
+    "image/jpeg",
+    "image/gif",
+    "image/webp",
+    "text/css",
+    "text/plain",
+    "font/woff2",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PageServiceState {
+    #[serde(default)]
+    pub pages: HashMap<String, SitePage>,
+    #[serde(default)]
+    pub index: String,
+    #[serde(default)]
+    pub assets: HashMap<String, PageAsset>,
+    // Revision urls are cached as immutable, so numbers are shared by every
+    // page in the service and never reused, even after a delete or rename
+    #[serde(default)]
+    pub next_revision: u64,
+    // the single page saved before multi-page sites, moved into pages on load
+    #[serde(default, rename = "page", skip_serializing)]
+    legacy_page: Option<String>,
+    // set by anything that changes saved state, cleared once it's been saved
+    #[serde(skip)]
+    pub dirty: bool,
+    // whether pagehost is serving this site, None until checked after a load
+    #[serde(skip)]
+    pub published: Option<bool>,
 }
 
 impl PageServiceState {
     fn new() -> Self {
+        let mut pages = HashMap::new();
+        pages.insert(DEFAULT_INDEX.to_string(), SitePage::new(DEFAULT_PAGE.to_string()));
         PageServiceState {
-            page: DEFAULT_PAGE.to_string(),
+            pages,
+            index: DEFAULT_INDEX.to_string(),
+            assets: HashMap::new(),
+            next_revision: 1,
+            legacy_page: None,
+            dirty: true,
+            published: None,
         }
     }
 
-    fn handle_subscribe(&mut self, _subscriber_node: String, our: &Address, service: &Service) -> anyhow::Result<()> {
-        let upd = PageUpdate::Page(self.page.clone());
-        update_subscribers(AppUpdate::Page(upd), our, service)?;
+    // Saves from before multi-page sites only hold `page`, which becomes the index
+    fn upgrade(&mut self) {
+        if let Some(page) = self.legacy_page.take() {
+            self.pages.insert(DEFAULT_INDEX.to_string(), SitePage::new(page));
+            self.index = DEFAULT_INDEX.to_string();
+            self.dirty = true;
+        }
+        if !self.pages.contains_key(&self.index) {
+            self.index = DEFAULT_INDEX.to_string();
+            self.pages.entry(self.index.clone())
+                .or_insert_with(|| SitePage::new(DEFAULT_PAGE.to_string()));
+            self.dirty = true;
+        }
+        // saves from before service-wide numbering continue past their highest revision
+        if self.next_revision == 0 {
+            let highest = self.pages.values().map(SitePage::current_revision).max().unwrap_or(0);
+            self.next_revision = highest + 1;
+            self.dirty = true;
+        }
+        for site_page in self.pages.values_mut() {
+            let excess = site_page.revisions.len().saturating_sub(MAX_REVISIONS_PER_PAGE);
+            if excess > 0 {
+                site_page.revisions.drain(..excess);
+                self.dirty = true;
+            }
+        }
+    }
+
+    fn take_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.dirty)
+    }
+
+    fn site_update(&self) -> PageUpdate {
+        let mut paths: Vec<String> = self.pages.keys().cloned().collect();
+        paths.sort();
+        PageUpdate::Site {
+            index: self.index.clone(),
+            paths,
+        }
+    }
+
+    fn handle_subscribe(&mut self, subscriber_node: String, our: &Address, service: &Service) -> anyhow::Result<()> {
+        update_subscriber(AppUpdate::Page(self.site_update()), &subscriber_node, our, service)?;
+        self.send_page(&self.index, &subscriber_node, our, service)?;
+        Ok(())
+    }
+
+    fn send_page(&self, path: &str, subscriber_node: &str, our: &Address, service: &Service) -> anyhow::Result<()> {
+        let Some(site_page) = self.pages.get(path) else {
+            let upd = PageUpdate::PageNotFound(path.to_string());
+            return update_subscriber(AppUpdate::Page(upd), subscriber_node, our, service);
+        };
+        let upd = PageUpdate::Page {
+            path: path.to_string(),
+            page: site_page.page.clone(),
+        };
+        update_subscriber(AppUpdate::Page(upd), subscriber_node, our, service)?;
+        if self.published == Some(true) {
+            let url_upd = PageUpdate::PublicUrl {
+                path: path.to_string(),
+                url: public_url(our, &public_page_path(service, path)),
+                revision: site_page.current_revision(),
+            };
+            update_subscriber(AppUpdate::Page(url_upd), subscriber_node, our, service)?;
+        }
         Ok(())
     }
 
     fn handle_request(&mut self, from: String, req: PageRequest, our: &Address, service: &Service) -> anyhow::Result<()> {
+        match &req {
+            PageRequest::RequestPage(path) => return self.send_page(path, &from, our, service),
+            PageRequest::RequestAsset(name) => return self.send_asset(name, &from, our, service),
+            _ => {}
+        }
         if from != our.node() {
             return Ok(());
         }
         match req {
-            PageRequest::EditPage(new_page) => {
-                self.page = new_page.clone();
-                let upd = PageUpdate::Page(new_page);
+            PageRequest::RequestPage(_) | PageRequest::RequestAsset(_) => {}
+            PageRequest::EditPage { path, page } => {
+                if !self.pages.contains_key(&path) {
+                    return Ok(());
+                }
+                self.write_page(&path, page, our, service)?;
+            }
+            PageRequest::CreatePage { path, page } => {
+                if !self.add_page(&path) {
+                    return Ok(());
+                }
+                self.write_page(&path, page, our, service)?;
+                update_subscribers(AppUpdate::Page(self.site_update()), our, service)?;
+            }
+            PageRequest::RenamePage { from: old_path, to: new_path } => {
+                if !self.rename_page(&old_path, &new_path) {
+                    return Ok(());
+                }
+                if self.published == Some(true) {
+                    let site_page = &self.pages[&new_path];
+                    unpublish_site_page(our, service, &old_path, site_page)?;
+                    publish_site_page(our, service, &new_path, site_page)?;
+                    if self.index == new_path {
+                        publish_site_root(our, service, &self.index)?;
+                    }
+                }
+                let upd = PageUpdate::RenamedPage {
+                    from: old_path,
+                    to: new_path,
+                };
                 update_subscribers(AppUpdate::Page(upd), our, service)?;
+                update_subscribers(AppUpdate::Page(self.site_update()), our, service)?;
+            }
+            PageRequest::DeletePage(path) => {
+                let Some(site_page) = self.remove_page(&path) else {
+                    return Ok(());
+                };
+                if self.published == Some(true) {
+                    unpublish_site_page(our, service, &path, &site_page)?;
+                }
+                update_subscribers(AppUpdate::Page(PageUpdate::DeletedPage(path)), our, service)?;
+                update_subscribers(AppUpdate::Page(self.site_update()), our, service)?;
+            }
+            PageRequest::SetIndex(path) => {
+                if !self.set_index(&path) {
+                    return Ok(());
+                }
+                if self.published == Some(true) {
+                    publish_site_root(our, service, &self.index)?;
+                }
+                update_subscribers(AppUpdate::Page(self.site_update()), our, service)?;
+            }
+            PageRequest::UploadAsset { name, mime, data } => {
+                let data = match self.decode_asset(&name, &mime, &data) {
+                    Ok(data) => data,
+                    Err(reason) => {
+                        let upd = PageUpdate::AssetRejected { name, reason };
+                        return update_subscriber(AppUpdate::Page(upd), &from, our, service);
+                    }
+                };
+                let file = vfs::create_file(&asset_file_path(our, service, &name)?, Some(5))?;
+                file.write(&data)?;
+
+                let asset = PageAsset {
+                    url: public_url(our, &public_asset_path(service, &name)),
+                    name,
+                    mime,
+                    size: data.len() as u64,
+                    uploaded_at: get_now(),
+                };
+                self.add_asset(asset.clone());
+                if self.published == Some(true) {
+                    publish(our, &public_asset_path(service, &asset.name), &asset.mime, CachePolicy::Revalidate, data)?;
+                }
+                update_subscribers(AppUpdate::Page(PageUpdate::NewAsset(asset)), our, service)?;
+            }
+            PageRequest::DeleteAsset(name) => {
+                if !self.remove_asset(&name) {
+                    return Ok(());
+                }
+                vfs::remove_file(&asset_file_path(our, service, &name)?, Some(5))?;
+                if self.published == Some(true) {
+                    poke(&pagehost_address(our), PageHostRequest::Unpublish(public_asset_path(service, &name)))?;
+                }
+                update_subscribers(AppUpdate::Page(PageUpdate::DeletedAsset(name)), our, service)?;
+            }
+            PageRequest::ListAssets => {
+                let mut assets: Vec<PageAsset> = self.assets.values().cloned().collect();
+                assets.sort_by(|a, b| a.name.cmp(&b.name));
+                update_subscriber(AppUpdate::Page(PageUpdate::Assets(assets)), &from, our, service)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Checks the upload and decodes it, refusing oversized data before decoding
+    fn decode_asset(&self, name: &str, mime: &str, encoded: &str) -> Result<Vec<u8>, String> {
+        if !is_valid_asset_name(name) {
+            return Err("invalid asset name".to_string());
+        }
+        if !ALLOWED_ASSET_MIMES.contains(&mime) {
+            return Err(format!("unsupported asset type {}", mime));
+        }
+        let size_error = || format!("assets must be between 1 and {} bytes", MAX_ASSET_SIZE);
+        if encoded.is_empty() || encoded.len() > MAX_ASSET_SIZE.div_ceil(3) * 4 {
+            return Err(size_error());
+        }
+        if !self.assets.contains_key(name) && self.assets.len() >= MAX_ASSETS {
+            return Err(format!("a page can hold at most {} assets", MAX_ASSETS));
+        }
+        let data = BASE64.decode(encoded).map_err(|_| "asset data isn't valid base64".to_string())?;
+        if data.is_empty() || data.len() > MAX_ASSET_SIZE {
+            return Err(size_error());
+        }
+        Ok(data)
+    }
+
+    // Pagehost only serves public sites, so subscribers to the rest fetch
+    // assets through the service, which already checks their access
+    fn send_asset(&self, name: &str, subscriber_node: &str, our: &Address, service: &Service) -> anyhow::Result<()> {
+        let Some(asset) = self.assets.get(name) else {
+            return Ok(());
+        };
+        let file = vfs::open_file(&asset_file_path(our, service, name)?, false, Some(5))?;
+        let upd = PageUpdate::AssetData {
+            name: asset.name.clone(),
+            mime: asset.mime.clone(),
+            data: BASE64.encode(file.read()?),
+        };
+        update_subscriber(AppUpdate::Page(upd), subscriber_node, our, service)
+    }
+
+    // The methods below are the only ones that change saved state, and each
+    // marks the state dirty itself before anything else can fail
+
+    fn add_page(&mut self, path: &str) -> bool {
+        if !is_valid_page_path(path) || self.pages.contains_key(path) {
+            return false;
+        }
+        self.dirty = true;
+        self.pages.insert(path.to_string(), SitePage::new(String::new()));
+        true
+    }
+
+    // Sets a page's text and records it as a new revision. Returns the new
+    // revision number and the numbers of any revisions dropped to make room.
+    fn set_page(&mut self, path: &str, page: String, now: u64) -> Option<(u64, Vec<u64>)> {
+        let site_page = self.pages.get_mut(path)?;
+        self.dirty = true;
+        let revision = self.next_revision;
+        self.next_revision += 1;
+        site_page.page = page.clone();
+        site_page.revisions.push(PageRevision {
+            revision,
+            time: now,
+            page,
+        });
+        let excess = site_page.revisions.len().saturating_sub(MAX_REVISIONS_PER_PAGE);
+        let dropped = site_page.revisions.drain(..excess).map(|r| r.revision).collect();
+        Some((revision, dropped))
+    }
+
+    fn rename_page(&mut self, old_path: &str, new_path: &str) -> bool {
+        if !is_valid_page_path(new_path) || self.pages.contains_key(new_path) {
+            return false;
+        }
+        let Some(site_page) = self.pages.remove(old_path) else {
+            return false;
+        };
+        self.dirty = true;
+        self.pages.insert(new_path.to_string(), site_page);
+        if self.index == old_path {
+            self.index = new_path.to_string();
+        }
+        true
+    }
+
+    // The index page can be renamed but never removed
+    fn remove_page(&mut self, path: &str) -> Option<SitePage> {
+        if path == self.index {
+            return None;
+        }
+        let site_page = self.pages.remove(path)?;
+        self.dirty = true;
+        Some(site_page)
+    }
+
+    fn set_index(&mut self, path: &str) -> bool {
+        if !self.pages.contains_key(path) {
+            return false;
+        }
+        self.dirty = true;
+        self.index = path.to_string();
+        true
+    }
+
+    fn add_asset(&mut self, asset: PageAsset) {
+        self.dirty = true;
+        self.assets.insert(asset.name.clone(), asset);
+    }
+
+    fn remove_asset(&mut self, name: &str) -> bool {
+        if self.assets.remove(name).is_none() {
+            return false;
+        }
+        self.dirty = true;
+        true
+    }
+
+    fn write_page(&mut self, path: &str, page: String, our: &Address, service: &Service) -> anyhow::Result<()> {
+        let Some((revision, dropped)) = self.set_page(path, page.clone(), get_now()) else {
+            return Ok(());
+        };
+        if self.published == Some(true) {
+            publish_page(our, &public_page_path(service, path), &page, CachePolicy::Revalidate)?;
+            publish_page(our, &public_revision_path(service, path, revision), &page, CachePolicy::Immutable)?;
+            for old_revision in dropped {
+                poke(&pagehost_address(our), PageHostRequest::Unpublish(public_revision_path(service, path, old_revision)))?;
             }
         }
+
+        let upd = PageUpdate::Page {
+            path: path.to_string(),
+            page,
+        };
+        update_subscribers(AppUpdate::Page(upd), our, service)?;
+        if self.published == Some(true) {
+            let url_upd = PageUpdate::PublicUrl {
+                path: path.to_string(),
+                url: public_url(our, &public_page_path(service, path)),
+                revision,
+            };
+            update_subscribers(AppUpdate::Page(url_upd), our, service)?;
+        }
+        Ok(())
+    }
+
+    // Only public services are served over plain HTTP, since those requests
+    // carry no node identity to check against the access settings. Publishes
+    // or withdraws the whole site whenever that changes.
+    fn sync_publication(&mut self, our: &Address, service: &Service) -> anyhow::Result<()> {
+        let is_public = matches!(service.meta.access, ServiceAccess::Public);
+        if self.published == Some(is_public) {
+            return Ok(());
+        }
+        if is_public {
+            self.publish_site(our, service)?;
+        } else {
+            poke(&pagehost_address(our), PageHostRequest::UnpublishTree(public_site_path(service)))?;
+        }
+        self.published = Some(is_public);
         Ok(())
     }
+
+    fn publish_site(&mut self, our: &Address, service: &Service) -> anyhow::Result<()> {
+        // clears anything served before that the site no longer has, like dropped revisions
+        poke(&pagehost_address(our), PageHostRequest::UnpublishTree(public_site_path(service)))?;
+        for (path, site_page) in &self.pages {
+            publish_site_page(our, service, path, site_page)?;
+        }
+        for asset in self.assets.values_mut() {
+            let file = vfs::open_file(&asset_file_path(our, service, &asset.name)?, false, Some(5))?;
+            let path = public_asset_path(service, &asset.name);
+            publish(our, &path, &asset.mime, CachePolicy::Revalidate, file.read()?)?;
+            // urls saved before pagehost served them lack its prefix
+            let url = public_url(our, &path);
+            if asset.url != url {
+                self.dirty = true;
+                asset.url = url;
+            }
+        }
+        publish_site_root(our, service, &self.index)
+    }
+}
+
+// Page paths become url segments, so keep them to a conservative charset.
+// '@' is left out since it separates a path from its revision.
+fn is_valid_page_path(path: &str) -> bool {
+    !path.is_empty()
+        && path.len() <= MAX_PAGE_PATH_LENGTH
+        && path.split('/').next() != Some(ASSETS_URL_SEGMENT)
+        && !path.starts_with('/')
+        && !path.ends_with('/')
+        && !path.contains("//")
+        && path.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '/')
+}
+
+const PUBLIC_PAGE_MIME: &str = "text/html; charset=utf-8";
+
+fn public_site_path(service: &Service) -> String {
+    format!("/public/{}", service.id.name)
+}
+
+fn public_page_path(service: &Service, path: &str) -> String {
+    format!("/public/{}/{}", service.id.name, path)
+}
+
+fn public_revision_path(service: &Service, path: &str, revision: u64) -> String {
+    format!("/public/{}/{}@{}", service.id.name, path, revision)
+}
+
+fn public_asset_path(service: &Service, name: &str) -> String {
+    format!("/public/{}/{}/{}", service.id.name, ASSETS_URL_SEGMENT, name)
+}
+
+fn is_valid_asset_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_ASSET_NAME_LENGTH
+        && !name.starts_with('.')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+// Assets live in the package's "assets" drive, one directory per service
+fn asset_file_path(our: &Address, service: &Service, name: &str) -> anyhow::Result<String> {
+    let drive = vfs::create_drive(our.package_id(), ASSETS_DRIVE, Some(5))?;
+    let dir = format!("{}/{}", drive, service.id.name);
+    vfs::open_dir(&dir, true, Some(5))?;
+    Ok(format!("{}/{}", dir, name))
+}
+
+// Requests to pagehost, the process that answers public HTTP GETs for us
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum PageHostRequest {
+    Publish {
+        path: String,
+        mime: String,
+        cache: CachePolicy,
+    },
+    Unpublish(String),
+    UnpublishTree(String),
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum CachePolicy {
+    Immutable,
+    Revalidate,
+}
+
+fn pagehost_address(our: &Address) -> Address {
+    Address::new(our.node(), ("pagehost", our.package(), our.publisher()))
+}
+
+// http-server serves pagehost's bindings under its process id
+fn public_url(our: &Address, path: &str) -> String {
+    format!("/pagehost:{}{}", our.package_id(), path)
+}
+
+fn publish(our: &Address, path: &str, mime: &str, cache: CachePolicy, content: Vec<u8>) -> anyhow::Result<()> {
+    let request = PageHostRequest::Publish {
+        path: path.to_string(),
+        mime: mime.to_string(),
+        cache,
+    };
+    Request::to(pagehost_address(our))
+        .body(serde_json::to_vec(&request)?)
+        .blob_bytes(content)
+        .send()
+}
+
+fn publish_page(our: &Address, path: &str, page: &str, cache: CachePolicy) -> anyhow::Result<()> {
+    publish(our, path, PUBLIC_PAGE_MIME, cache, page.as_bytes().to_vec())
+}
+
+// The current path can change on every edit, while a revision path never does
+fn publish_site_page(our: &Address, service: &Service, path: &str, site_page: &SitePage) -> anyhow::Result<()> {
+    publish_page(our, &public_page_path(service, path), &site_page.page, CachePolicy::Revalidate)?;
+    for revision in &site_page.revisions {
+        publish_page(our, &public_revision_path(service, path, revision.revision), &revision.page, CachePolicy::Immutable)?;
+    }
+    Ok(())
+}
+
+fn unpublish_site_page(our: &Address, service: &Service, path: &str, site_page: &SitePage) -> anyhow::Result<()> {
+    let pagehost = pagehost_address(our);
+    poke(&pagehost, PageHostRequest::Unpublish(public_page_path(service, path)))?;
+    for revision in &site_page.revisions {
+        poke(&pagehost, PageHostRequest::Unpublish(public_revision_path(service, path, revision.revision)))?;
+    }
+    Ok(())
+}
+
+// The site root redirects into the site directory, so that relative links
+// written in the index page resolve against the other pages.
+fn publish_site_root(our: &Address, service: &Service, index: &str) -> anyhow::Result<()> {
+    let redirect = format!(
+        r#"<!DOCTYPE html><html><head><meta http-equiv="refresh" content="0; url={}/{}"></head></html>"#,
+        service.id.name, index
+    );
+    publish_page(our, &public_site_path(service), &redirect, CachePolicy::Revalidate)
 }
 
 call_init!(init);
@@ -145,6 +764,7 @@ fn init(our: Address) {
         .expect("failed to bind ws");
 
     loop {
+        handle_timers(&our, &mut state);
         match provider_handle_message(&our, &mut state.provider) {
             Ok(()) => {}
             Err(e) => {
@@ -152,4 +772,175 @@ fn init(our: Address) {
             }
         };
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Loads saved json the way init does, after default_load_service
+    fn load(saved: &str) -> PageServiceState {
+        let mut state: PageServiceState = serde_json::from_str(saved).unwrap();
+        state.upgrade();
+        state
+    }
+
+    // The same json that default_save_service writes, loaded back through init's
+    // path. A current save must load without needing another one.
+    fn reload(state: &PageServiceState) -> PageServiceState {
+        let mut loaded = load(&serde_json::to_string(state).unwrap());
+        assert!(!loaded.take_dirty(), "current save was upgraded on load");
+        loaded
+    }
+
+    // A change has to mark the state dirty, and has to survive a save and load
+    fn assert_saved(state: &mut PageServiceState) -> PageServiceState {
+        assert!(state.take_dirty(), "change was not marked dirty");
+        let loaded = reload(state);
+        assert_eq!(serde_json::to_value(&loaded).unwrap(), serde_json::to_value(&*state).unwrap());
+        loaded
+    }
+
+    fn clean_state() -> PageServiceState {
+        let mut state = PageServiceState::new();
+        state.take_dirty();
+        state
+    }
+
+    #[test]
+    fn new_state_is_saved() {
+        let mut state = PageServiceState::new();
+        let loaded = assert_saved(&mut state);
+        assert_eq!(loaded.index, DEFAULT_INDEX);
+    }
+
+    #[test]
+    fn add_page_is_saved() {
+        let mut state = clean_state();
+        assert!(state.add_page("about"));
+        let loaded = assert_saved(&mut state);
+        assert!(loaded.pages.contains_key("about"));
+    }
+
+    #[test]
+    fn set_page_is_saved_with_its_revision() {
+        let mut state = clean_state();
+        assert_eq!(state.set_page(DEFAULT_INDEX, "hello".to_string(), 10), Some((1, vec![])));
+        let loaded = assert_saved(&mut state);
+        let site_page = &loaded.pages[DEFAULT_INDEX];
+        assert_eq!(site_page.page, "hello");
+        assert_eq!(site_page.current_revision(), 1);
+        assert_eq!(loaded.next_revision, 2);
+    }
+
+    #[test]
+    fn revision_numbers_are_never_reused() {
+        let mut state = clean_state();
+        state.add_page("about");
+        state.set_page("about", "first".to_string(), 10);
+        state.remove_page("about");
+        state.add_page("about");
+        let (revision, _) = state.set_page("about", "second".to_string(), 11).unwrap();
+        assert_eq!(revision, 2);
+    }
+
+    #[test]
+    fn old_revisions_are_dropped() {
+        let mut state = clean_state();
+        for i in 0..MAX_REVISIONS_PER_PAGE as u64 {
+            state.set_page(DEFAULT_INDEX, i.to_string(), i);
+        }
+        let (_, dropped) = state.set_page(DEFAULT_INDEX, "last".to_string(), 100).unwrap();
+        assert_eq!(dropped, vec![1]);
+        assert_eq!(state.pages[DEFAULT_INDEX].revisions.len(), MAX_REVISIONS_PER_PAGE);
+    }
+
+    #[test]
+    fn rename_page_is_saved_and_moves_the_index() {
+        let mut state = clean_state();
+        assert!(state.rename_page(DEFAULT_INDEX, "home"));
+        let loaded = assert_saved(&mut state);
+        assert_eq!(loaded.index, "home");
+        assert!(loaded.pages.contains_key("home"));
+        assert!(!loaded.pages.contains_key(DEFAULT_INDEX));
+    }
+
+    #[test]
+    fn remove_page_is_saved() {
+        let mut state = clean_state();
+        state.add_page("about");
+        state.take_dirty();
+        assert!(state.remove_page("about").is_some());
+        let loaded = assert_saved(&mut state);
+        assert!(!loaded.pages.contains_key("about"));
+    }
+
+    #[test]
+    fn set_index_is_saved() {
+        let mut state = clean_state();
+        state.add_page("about");
+        state.take_dirty();
+        assert!(state.set_index("about"));
+        let loaded = assert_saved(&mut state);
+        assert_eq!(loaded.index, "about");
+    }
+
+    #[test]
+    fn assets_are_saved() {
+        let mut state = clean_state();
+        state.add_asset(PageAsset {
+            name: "logo.png".to_string(),
+            mime: "image/png".to_string(),
+            size: 3,
+            url: "/pagehost:dartfrog:gliderlabs.os/public/site/assets/logo.png".to_string(),
+            uploaded_at: 10,
+        });
+        let loaded = assert_saved(&mut state);
+        assert!(loaded.assets.contains_key("logo.png"));
+        assert!(state.remove_asset("logo.png"));
+        let loaded = assert_saved(&mut state);
+        assert!(loaded.assets.is_empty());
+    }
+
+    #[test]
+    fn refused_changes_stay_clean() {
+        let mut state = clean_state();
+        assert!(!state.add_page("not a path"));
+        assert!(!state.add_page(DEFAULT_INDEX));
+        assert!(state.set_page("missing", String::new(), 10).is_none());
+        assert!(!state.rename_page("missing", "other"));
+        assert!(state.remove_page(DEFAULT_INDEX).is_none());
+        assert!(!state.set_index("missing"));
+        assert!(!state.remove_asset("missing"));
+        assert!(!state.take_dirty());
+    }
+
+    #[test]
+    fn single_page_save_is_upgraded() {
+        let mut state = load(r#"{"page":"hello"}"#);
+        let loaded = assert_saved(&mut state);
+        assert_eq!(loaded.index, DEFAULT_INDEX);
+        assert_eq!(loaded.pages[DEFAULT_INDEX].page, "hello");
+        assert!(serde_json::to_value(&loaded).unwrap().get("page").is_none());
+    }
+
+    #[test]
+    fn per_page_revision_save_is_upgraded() {
+        let saved = r#"{"pages":{"index":{"page":"v3","revisions":[{"revision":2,"time":1,"page":"v2"},{"revision":3,"time":2,"page":"v3"}]},"about":{"page":"a","revisions":[{"revision":1,"time":1,"page":"a"}]}},"index":"index","assets":{}}"#;
+        let mut state = load(saved);
+        let loaded = assert_saved(&mut state);
+        assert_eq!(loaded.next_revision, 4);
+        assert_eq!(loaded.pages["index"].page, "v3");
+        assert_eq!(loaded.pages["about"].current_revision(), 1);
+    }
+
+    #[test]
+    fn missing_index_page_is_restored() {
+        let saved = r#"{"pages":{"about":{"page":"a","revisions":[]}},"index":"gone","assets":{},"next_revision":1}"#;
+        let mut state = load(saved);
+        let loaded = assert_saved(&mut state);
+        assert_eq!(loaded.index, DEFAULT_INDEX);
+        assert!(loaded.pages.contains_key("about"));
+        assert_eq!(loaded.pages[DEFAULT_INDEX].page, DEFAULT_PAGE);
+    }
+}
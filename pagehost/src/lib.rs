@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+
+use hyperware_process_lib::{await_message, call_init, get_typed_state, get_blob, println, set_state, vfs, Address, Message};
+use hyperware_process_lib::http::{server::{self, HttpServerRequest}, StatusCode};
+use serde::{Serialize, Deserialize};
+
+wit_bindgen::generate!({
+    path: "target/wit",
+    world: "process-v1",
+});
+
+// Serves the public copies of page services over plain HTTP. Static bindings
+// can't carry response headers, so this process answers each GET itself with
+// the caching policy the page process asked for. Only page may publish here,
+// and it only publishes services whose access is public.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum PageHostRequest {
+    // the content comes in the blob
+    Publish {
+        path: String,
+        mime: String,
+        cache: CachePolicy,
+    },
+    Unpublish(String),
+    // the path and everything beneath it
+    UnpublishTree(String),
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum CachePolicy {
+    // content at this path never changes, e.g. a page revision
+    Immutable,
+    // content can change, so clients have to check back every time
+    Revalidate,
+}
+
+impl CachePolicy {
+    fn header(&self) -> &'static str {
+        match self {
+            CachePolicy::Immutable => "public, max-age=31536000, immutable",
+            CachePolicy::Revalidate => "no-cache",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PublishedPath {
+    mime: String,
+    cache: CachePolicy,
+}
+
+// Content lives in the vfs, only the table of paths is kept in process state
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct PageHostState {
+    paths: HashMap<String, PublishedPath>,
+}
+
+const CONTENT_DRIVE: &str = "public";
+
+impl PageHostState {
+    fn save(&self) {
+        set_state(&bincode::serialize(self).unwrap());
+    }
+
+    fn load() -> Self {
+        get_typed_state(|bytes| bincode::deserialize::<PageHostState>(bytes)).unwrap_or_default()
+    }
+}
+
+// One file per path, named by the hex of the path so nested paths and
+// revision suffixes don't need directories
+fn content_file(our: &Address, path: &str) -> anyhow::Result<String> {
+    let drive = vfs::create_drive(our.package_id(), CONTENT_DRIVE, Some(5))?;
+    let name: String = path.bytes().map(|b| format!("{:02x}", b)).collect();
+    Ok(format!("{}/{}", drive, name))
+}
+
+fn bind_path(path: &str) -> anyhow::Result<()> {
+    let config = server::HttpBindingConfig::default().authenticated(false);
+    let mut http_server = server::HttpServer::new(5);
+    http_server.bind_http_path(path, config)?;
+    Ok(())
+}
+
+fn unpublish(our: &Address, state: &mut PageHostState, path: &str) -> anyhow::Result<()> {
+    if state.paths.remove(path).is_none() {
+        return Ok(());
+    }
+    let mut http_server = server::HttpServer::new(5);
+    http_server.unbind_http_path(path)?;
+    vfs::remove_file(&content_file(our, path)?, Some(5))?;
+    Ok(())
+}
+
+fn handle_page_request(our: &Address, state: &mut PageHostState, request: PageHostRequest) -> anyhow::Result<()> {
+    match request {
+        PageHostRequest::Publish { path, mime, cache } => {
+            let Some(blob) = get_blob() else {
+                return Err(anyhow::anyhow!("publish without content: {}", path));
+            };
+            let file = vfs::create_file(&content_file(our, &path)?, Some(5))?;
+            file.write(&blob.bytes)?;
+            if !state.paths.contains_key(&path) {
+                bind_path(&path)?;
+            }
+            state.paths.insert(path, PublishedPath { mime, cache });
+        }
+        PageHostRequest::Unpublish(path) => {
+            unpublish(our, state, &path)?;
+        }
+        PageHostRequest::UnpublishTree(root) => {
+            let prefix = format!("{}/", root);
+            let paths: Vec<String> = state.paths.keys()
+                .filter(|path| **path == root || path.starts_with(&prefix))
+                .cloned()
+                .collect();
+            for path in paths {
+                unpublish(our, state, &path)?;
+            }
+        }
+    }
+    state.save();
+    Ok(())
+}
+
+fn handle_http_request(our: &Address, state: &PageHostState, body: &[u8]) -> anyhow::Result<()> {
+    let Ok(HttpServerRequest::Http(request)) = serde_json::from_slice::<HttpServerRequest>(body) else {
+        return Ok(());
+    };
+    let path = request.bound_path(Some(&our.process.to_string())).to_string();
+    let Some(published) = state.paths.get(&path) else {
+        server::send_response(StatusCode::NOT_FOUND, None, vec![]);
+        return Ok(());
+    };
+    if request.method()?.as_str() != "GET" {
+        server::send_response(StatusCode::METHOD_NOT_ALLOWED, None, vec![]);
+        return Ok(());
+    }
+    let content = vfs::open_file(&content_file(our, &path)?, false, Some(5))?.read()?;
+    let headers = HashMap::from([
+        ("Content-Type".to_string(), published.mime.clone()),
+        ("Cache-Control".to_string(), published.cache.header().to_string()),
+    ]);
+    server::send_response(StatusCode::OK, Some(headers), content);
+    Ok(())
+}
+
+fn handle_message(our: &Address, state: &mut PageHostState) -> anyhow::Result<()> {
+    let message = await_message()?;
+    let Message::Request { ref source, ref body, .. } = message else {
+        return Ok(());
+    };
+    if source.node != our.node {
+        return Ok(());
+    }
+    if source.process.to_string() == "http-server:distro:sys" {
+        return handle_http_request(our, state, body);
+    }
+    let is_page = source.process.process() == "page" && source.package_id() == our.package_id();
+    if !is_page {
+        return Ok(());
+    }
+    let request = serde_json::from_slice::<PageHostRequest>(body)?;
+    handle_page_request(our, state, request)
+}
+
+call_init!(init);
+fn init(our: Address) {
+    println!("init pagehost");
+    let mut state = PageHostState::load();
+
+    // http-server bindings don't survive a restart
+    for path in state.paths.keys() {
+        if let Err(e) = bind_path(path) {
+            println!("pagehost error binding {}: {:?}", path, e);
+        }
+    }
+
+    loop {
+        if let Err(e) = handle_message(&our, &mut state) {
+            println!("pagehost error handling message: {:?}", e);
+        }
+    }
+}
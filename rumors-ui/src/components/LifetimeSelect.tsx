@@ -0,0 +1,22 @@
+import React from 'react';
+import { RumorLifetime } from '../store/rumors';
+
+// Empty means the rumor stays until retention archives it
+const LifetimeSelect: React.FC<{
+  value: RumorLifetime | null;
+  onChange: (lifetime: RumorLifetime | null) => void;
+}> = ({ value, onChange }) => (
+  <select
+    value={value ?? ""}
+    onChange={(e) => onChange(e.target.value ? e.target.value as RumorLifetime : null)}
+    title="delete this rumor after"
+    style={{ margin: "0px" }}
+  >
+    <option value="">keep</option>
+    <option value="Hour">1 hour</option>
+    <option value="Day">1 day</option>
+    <option value="Week">1 week</option>
+  </select>
+);
+
+export default LifetimeSelect;
@@ -1,6 +1,7 @@
 import React, { useEffect, useState } from 'react';
 import { BrowserRouter as Router, Route, Routes, Link, useParams, useNavigate } from 'react-router-dom';
-import useRumorsStore, { Rumor } from '../store/rumors';
+import useRumorsStore, { Rumor, RumorLifetime } from '../store/rumors';
+import LifetimeSelect from './LifetimeSelect';
 import { dfLinkRegex, dfLinkToRealLink, ServiceID, useServiceStore, HomeIcon, isImageUrl} from '@dartfrog/puddle';
 
 const Field: React.FC<{ label: string; value: string | number }> = ({ label, value }) => (
@@ -61,8 +62,12 @@ const PostView: React.FC<{ isAdmin: boolean }> = ({ isAdmin }) => {
   );
 };
 
+// Salted author keys keep node names out of what the host stores, but the
+// host still receives every rumor from the sending node
+const STRONG_MODE_LIMITATION = "Authors are saved only as salted hashes. The host still sees which node sends each rumor as it arrives, and can check a suspected node against the stored hashes.";
+
 const BannedUsersList: React.FC = () => {
-  const { bannedUsers, banUser, unbanUser } = useRumorsStore();
+  const { bannedUsers, mutedUsers, banUser, unbanUser, anonymity, setAnonymityMode } = useRumorsStore();
   const { api } = useServiceStore();
   const navigate = useNavigate();
 
@@ -98,6 +103,31 @@ const BannedUsersList: React.FC = () => {
         <button onClick={handleUnban} style={{ marginLeft: '0.5rem' }}>Unban User</button>
       </div>
 
+      <div style={{ marginBottom: '1rem' }}>
+        <div>Anonymity:</div>
+        <label style={{ marginRight: '1rem' }}>
+          <input
+            type="radio"
+            checked={anonymity === "HostVisible"}
+            onChange={() => api && setAnonymityMode(api, "HostVisible")}
+          />
+          host can look up authors
+        </label>
+        <label>
+          <input
+            type="radio"
+            checked={anonymity === "Strong"}
+            onChange={() => api && setAnonymityMode(api, "Strong")}
+          />
+          store salted authors only
+        </label>
+        {anonymity === "Strong" &&
+          <p style={{ fontSize: '0.8rem', color: 'rgba(255,255,255,0.5)' }}>
+            {STRONG_MODE_LIMITATION}
+          </p>
+        }
+      </div>
+
       <div>Banned Users:</div>
       {bannedUsers.length > 0 ? (
         <ul>
@@ -116,6 +146,25 @@ const BannedUsersList: React.FC = () => {
       ) : (
         <p>No users are currently banned.</p>
       )}
+
+      <div>Muted Users:</div>
+      {mutedUsers.length > 0 ? (
+        <ul>
+          {mutedUsers.map((user, index) => (
+            <li key={index}>
+              {user}
+              <button
+                onClick={() => api && unbanUser(api, user)}
+                style={{ marginLeft: '0.5rem' }}
+              >
+                Unmute
+              </button>
+            </li>
+          ))}
+        </ul>
+      ) : (
+        <p>No users are currently muted.</p>
+      )}
     </div>
   );
 };
@@ -210,15 +259,17 @@ const DisplayRumor: React.FC<{ rumor: Rumor; isAdmin: boolean }> = ({ rumor, isA
 };
 
 const RumorsBox: React.FC = () => {
-  const { rumors, createRumor, bannedUsers } = useRumorsStore();
+  const { rumors, createRumor, bannedUsers, anonymity, rejection, clearRejection } = useRumorsStore();
   const { api, serviceId, serviceMetadata } = useServiceStore();
   const [inputValue, setInputValue] = useState('');
+  const [lifetime, setLifetime] = useState<RumorLifetime | null>(null);
   const [isAdmin, setIsAdmin] = useState(false);
 
   const handleSubmit = (e: React.FormEvent) => {
     e.preventDefault();
     if (inputValue.trim() && api) {
-      createRumor(api, inputValue.trim());
+      clearRejection();
+      createRumor(api, inputValue.trim(), lifetime ?? undefined);
       setInputValue('');
     }
   };
@@ -332,10 +383,21 @@ const RumorsBox: React.FC = () => {
                     margin: "0px",
                   }}
                 />
+                <LifetimeSelect value={lifetime} onChange={setLifetime} />
                 <button>
                   send
                 </button>
               </form>
+              {rejection &&
+                <div style={{ fontSize: "0.7rem", color: "#ff6b6b", maxWidth: "500px", marginBottom: "0.8rem" }}>
+                  {rejection}
+                </div>
+              }
+              {anonymity === "Strong" &&
+                <div style={{ fontSize: "0.7rem", color: "rgba(255,255,255,0.4)", maxWidth: "500px", marginBottom: "0.8rem" }}>
+                  {STRONG_MODE_LIMITATION}
+                </div>
+              }
               <div
                 style={{
                   flexGrow: 1,
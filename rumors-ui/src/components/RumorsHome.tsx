@@ -1,13 +1,12 @@
-import React, { useEffect, useState } from 'react';
+import React, { useEffect } from 'react';
 import { ServiceApi, useServiceStore } from '@dartfrog/puddle';
 import useRumorsStore from '../store/rumors';
 import { PROCESS_NAME, WEBSOCKET_URL } from '../utils';
 import { Routes, Route, Link, useLocation } from 'react-router-dom';
 
 const RumorsHome: React.FC = () => {
-  const { api, setApi, setIsClientConnected, setPeerMap } = useServiceStore();
-  const { createRumor, handleUpdate, rumors } = useRumorsStore();
-  const [newRumorText, setNewRumorText] = useState('');
+  const { setApi, setIsClientConnected, setPeerMap } = useServiceStore();
+  const { handleUpdate, rumors } = useRumorsStore();
 
   const ourNode = window.our?.node;
 
@@ -39,13 +38,6 @@ const RumorsHome: React.FC = () => {
   // Sort rumors by time (descending order)
   const sortedRumors = [...rumors].sort((a, b) => b.time - a.time);
 
-  const handleCreateRumor = () => {
-    if (newRumorText.trim()) {
-      createRumor(api, newRumorText.trim());
-      setNewRumorText('');
-    }
-  };
-
   const location = useLocation();
   const baseOrigin = window.origin.split(".").slice(1).join(".")
 
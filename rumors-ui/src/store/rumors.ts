@@ -8,14 +8,25 @@ export interface Rumor {
   text: string;
   time: number;
   source: string | null;
+  expires_at: number | null;
 }
 
+export type RumorLifetime = "Hour" | "Day" | "Week";
+
+export type AnonymityMode = "HostVisible" | "Strong";
+
 export interface RumorsStore {
   rumors: Rumor[]
   bannedUsers: string[]
-  createRumor: (api: ServiceApi, text: string) => void
+  mutedUsers: string[]
+  anonymity: AnonymityMode | null
+  // why the host turned down our last request, shown until the next one
+  rejection: string | null
+  clearRejection: () => void
+  createRumor: (api: ServiceApi, text: string, lifetime?: RumorLifetime) => void
   banUser: (api: ServiceApi, user: string) => void
   unbanUser: (api: ServiceApi, user: string) => void
+  setAnonymityMode: (api: ServiceApi, mode: AnonymityMode) => void
   deleteRumor: (api: ServiceApi, rumorId: number) => void
   getRumorAuthor: (api: ServiceApi, rumorId: number) => void
   handleUpdate: (update: any) => void
@@ -26,11 +37,16 @@ export interface RumorsStore {
 const useRumorsStore = create<RumorsStore>((set, get) => ({
   rumors: [],
   bannedUsers: [],
-  
-  createRumor: (api, text) => {
+  mutedUsers: [],
+  anonymity: null,
+  rejection: null,
+
+  clearRejection: () => set({ rejection: null }),
+
+  createRumor: (api, text, lifetime) => {
     const req = {
       "Rumors": {
-        "CreateNewRumor": text
+        "CreateNewRumor": { text, lifetime: lifetime ?? null }
       }
     };
     api.sendToService(req);
@@ -54,6 +70,15 @@ const useRumorsStore = create<RumorsStore>((set, get) => ({
     api.sendToService(req);
   },
 
+  setAnonymityMode: (api, mode) => {
+    const req = {
+      "Rumors": {
+        "SetAnonymityMode": mode
+      }
+    };
+    api.sendToService(req);
+  },
+
   deleteRumor: (api, rumorId) => {
     const req = {
       "Rumors": {
@@ -80,6 +105,10 @@ const useRumorsStore = create<RumorsStore>((set, get) => ({
       set((state) => ({ rumors: [update.NewRumor, ...state.rumors] }));
     } else if ('BannedUsers' in update) {
       set({ bannedUsers: update.BannedUsers });
+    } else if ('MutedUsers' in update) {
+      set({ mutedUsers: update.MutedUsers });
+    } else if ('AnonymityMode' in update) {
+      set({ anonymity: update.AnonymityMode });
     } else if ('DeletedRumor' in update) {
       set((state) => ({
         rumors: state.rumors.filter(rumor => rumor.id !== update.DeletedRumor)
@@ -92,6 +121,12 @@ const useRumorsStore = create<RumorsStore>((set, get) => ({
             : rumor
         )
       }));
+    } else if ('RumorRejected' in update) {
+      set({ rejection: update.RumorRejected.reason });
+    } else if ('RetentionRejected' in update) {
+      set({ rejection: update.RetentionRejected.reason });
+    } else if ('LimitsRejected' in update) {
+      set({ rejection: update.LimitsRejected.reason });
     } else {
       console.warn('Unknown update type:', update);
     }
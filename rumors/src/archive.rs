@@ -0,0 +1,290 @@
+use dartfrog_lib::Service;
+use hyperware_process_lib::{vfs, Address};
+use serde::{Serialize, Deserialize};
+
+use crate::Rumor;
+
+const ARCHIVE_DRIVE: &str = "archive";
+const INDEX_FILE: &str = "index.json";
+const CHUNK_SIZE: usize = 256;
+
+// Limits on how many rumors stay live in the service state. Anything past
+// them is moved into the archive rather than dropped, and the archive itself
+// drops its oldest rumors once it holds more than max_archived.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetentionSettings {
+    pub max_rumors: Option<usize>,
+    pub max_age: Option<u64>, // seconds
+    #[serde(default = "default_max_archived")]
+    pub max_archived: Option<usize>,
+}
+
+fn default_max_archived() -> Option<usize> {
+    Some(50_000)
+}
+
+impl Default for RetentionSettings {
+    fn default() -> Self {
+        RetentionSettings {
+            max_rumors: Some(1000),
+            max_age: None,
+            max_archived: default_max_archived(),
+        }
+    }
+}
+
+impl RetentionSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_rumors == Some(0) {
+            return Err("max_rumors must be at least 1".to_string());
+        }
+        if self.max_age == Some(0) {
+            return Err("max_age must be at least 1 second".to_string());
+        }
+        if self.max_archived == Some(0) {
+            return Err("max_archived must be at least 1, leave it out to keep everything".to_string());
+        }
+        Ok(())
+    }
+
+    // How many of the oldest live rumors fall outside the limits
+    pub fn excess(&self, rumors: &[Rumor], now: u64) -> usize {
+        let over_count = self.max_rumors
+            .map(|max| rumors.len().saturating_sub(max))
+            .unwrap_or(0);
+        let over_age = self.max_age
+            .map(|max_age| {
+                let cutoff = now.saturating_sub(max_age);
+                rumors.iter().take_while(|rumor| rumor.time < cutoff).count()
+            })
+            .unwrap_or(0);
+        over_count.max(over_age)
+    }
+}
+
+// The archive lives in the vfs as fixed-size chunks of rumors, oldest first,
+// plus an index of the id range each chunk covers. Only the chunks a query
+// touches are ever read.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct ArchiveIndex {
+    chunks: Vec<ChunkInfo>,
+    #[serde(default)]
+    next_file: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct ChunkInfo {
+    first_id: u64,
+    last_id: u64,
+    len: usize,
+    // chunk files keep their name when older chunks are pruned. Indexes
+    // written before pruning existed named each file by its position.
+    #[serde(default)]
+    file: Option<u64>,
+}
+
+impl ChunkInfo {
+    fn update(&mut self, chunk: &[Rumor]) {
+        if let (Some(first), Some(last)) = (chunk.first(), chunk.last()) {
+            self.first_id = first.id;
+            self.last_id = last.id;
+        }
+        self.len = chunk.len();
+    }
+}
+
+impl ArchiveIndex {
+    fn new_chunk(&mut self) {
+        self.chunks.push(ChunkInfo {
+            file: Some(self.next_file),
+            ..ChunkInfo::default()
+        });
+        self.next_file += 1;
+    }
+
+    fn last_file(&self) -> u64 {
+        self.chunks.last().and_then(|info| info.file).unwrap_or(0)
+    }
+}
+
+// Appends rumors, oldest first, then prunes whole chunks from the old end
+// while the rest still holds at least max_archived. Rumors archived before a
+// restart may still be in the last saved state, so anything at or below the
+// newest archived id is skipped.
+pub fn append(our: &Address, service: &Service, rumors: Vec<Rumor>, max_archived: Option<usize>) -> anyhow::Result<()> {
+    let dir = archive_dir(our, service)?;
+    let mut index = read_index(&dir)?;
+    let last_id = index.chunks.last().map(|info| info.last_id).unwrap_or(0);
+    let mut rumors = rumors.into_iter().filter(|rumor| rumor.id > last_id).peekable();
+    if rumors.peek().is_none() {
+        return Ok(());
+    }
+
+    let mut chunk = match index.chunks.last() {
+        Some(info) if info.len < CHUNK_SIZE => read_chunk(&dir, info)?,
+        _ => {
+            index.new_chunk();
+            Vec::new()
+        }
+    };
+    for rumor in rumors {
+        if chunk.len() >= CHUNK_SIZE {
+            write_chunk(&dir, index.last_file(), &chunk)?;
+            index.new_chunk();
+            chunk = Vec::new();
+        }
+        chunk.push(rumor);
+        if let Some(info) = index.chunks.last_mut() {
+            info.update(&chunk);
+        }
+    }
+    write_chunk(&dir, index.last_file(), &chunk)?;
+
+    let mut pruned = Vec::new();
+    if let Some(max_archived) = max_archived {
+        let mut total: usize = index.chunks.iter().map(|info| info.len).sum();
+        while index.chunks.len() > 1 && total - index.chunks[0].len >= max_archived {
+            let info = index.chunks.remove(0);
+            total -= info.len;
+            pruned.push(info);
+        }
+    }
+    // files only go once the index no longer points at them
+    write_index(&dir, &index)?;
+    for info in pruned {
+        vfs::remove_file(&chunk_path(&dir, info.file.unwrap_or(0)), Some(5))?;
+    }
+    Ok(())
+}
+
+// Up to `limit` archived rumors older than `before`, newest first
+pub fn older(our: &Address, service: &Service, before: u64, limit: usize) -> anyhow::Result<Vec<Rumor>> {
+    let dir = archive_dir(our, service)?;
+    let index = read_index(&dir)?;
+    let mut found = Vec::new();
+    for info in index.chunks.iter().rev() {
+        if found.len() >= limit {
+            break;
+        }
+        if info.len == 0 || info.first_id >= before {
+            continue;
+        }
+        let chunk = read_chunk(&dir, info)?;
+        let remaining = limit - found.len();
+        found.extend(chunk.into_iter().rev().filter(|rumor| rumor.id < before).take(remaining));
+    }
+    Ok(found)
+}
+
+// The archived rumor with this id, or the rumor holding the reply with this id
+pub fn find(our: &Address, service: &Service, id: u64) -> anyhow::Result<Option<Rumor>> {
+    let dir = archive_dir(our, service)?;
+    let index = read_index(&dir)?;
+    let Some((_, chunk)) = locate(&dir, &index, id)? else {
+        return Ok(None);
+    };
+    Ok(chunk.into_iter().find(|rumor| rumor.contains(id)))
+}
+
+// Removes an archived rumor or reply, returning whether it was there
+pub fn remove(our: &Address, service: &Service, id: u64) -> anyhow::Result<bool> {
+    let dir = archive_dir(our, service)?;
+    let mut index = read_index(&dir)?;
+    let Some((chunk_index, mut chunk)) = locate(&dir, &index, id)? else {
+        return Ok(false);
+    };
+    if let Some(position) = chunk.iter().position(|rumor| rumor.id == id) {
+        chunk.remove(position);
+    } else {
+        for rumor in chunk.iter_mut() {
+            rumor.remove_reply(id);
+        }
+    }
+    write_chunk(&dir, index.chunks[chunk_index].file.unwrap_or(0), &chunk)?;
+    index.chunks[chunk_index].update(&chunk);
+    write_index(&dir, &index)?;
+    Ok(true)
+}
+
+// Rewrites every stored author, used when switching to strong anonymity
+pub fn map_sources(our: &Address, service: &Service, f: &impl Fn(&str) -> String) -> anyhow::Result<()> {
+    let dir = archive_dir(our, service)?;
+    let index = read_index(&dir)?;
+    for info in &index.chunks {
+        let mut chunk = read_chunk(&dir, info)?;
+        for rumor in chunk.iter_mut() {
+            rumor.map_sources(f);
+        }
+        write_chunk(&dir, info.file.unwrap_or(0), &chunk)?;
+    }
+    Ok(())
+}
+
+// Rumor ids map straight to a chunk, but a reply's id can be newer than the
+// chunk its rumor landed in, so replies fall back to scanning every chunk
+fn locate(dir: &str, index: &ArchiveIndex, id: u64) -> anyhow::Result<Option<(usize, Vec<Rumor>)>> {
+    let by_range = index.chunks.iter().position(|info| {
+        info.len > 0 && info.first_id <= id && id <= info.last_id
+    });
+    if let Some(chunk_index) = by_range {
+        let chunk = read_chunk(dir, &index.chunks[chunk_index])?;
+        if chunk.iter().any(|rumor| rumor.contains(id)) {
+            return Ok(Some((chunk_index, chunk)));
+        }
+    }
+    for (chunk_index, info) in index.chunks.iter().enumerate().rev() {
+        if info.len == 0 || info.first_id > id || Some(chunk_index) == by_range {
+            continue;
+        }
+        let chunk = read_chunk(dir, info)?;
+        if chunk.iter().any(|rumor| rumor.contains(id)) {
+            return Ok(Some((chunk_index, chunk)));
+        }
+    }
+    Ok(None)
+}
+
+// One directory per service in the package's "archive" drive
+fn archive_dir(our: &Address, service: &Service) -> anyhow::Result<String> {
+    let drive = vfs::create_drive(our.package_id(), ARCHIVE_DRIVE, Some(5))?;
+    let dir = format!("{}/{}", drive, service.id.name);
+    vfs::open_dir(&dir, true, Some(5))?;
+    Ok(dir)
+}
+
+fn read_index(dir: &str) -> anyhow::Result<ArchiveIndex> {
+    let mut index: ArchiveIndex = match vfs::open_file(&format!("{}/{}", dir, INDEX_FILE), false, Some(5)) {
+        Ok(file) => serde_json::from_slice(&file.read()?)?,
+        Err(_) => ArchiveIndex::default(),
+    };
+    // nothing was ever pruned from older indexes, so positions are still file names
+    for (position, info) in index.chunks.iter_mut().enumerate() {
+        if info.file.is_none() {
+            info.file = Some(position as u64);
+        }
+    }
+    let next_file = index.chunks.iter().filter_map(|info| info.file).max().map_or(0, |file| file + 1);
+    index.next_file = index.next_file.max(next_file);
+    Ok(index)
+}
+
+fn write_index(dir: &str, index: &ArchiveIndex) -> anyhow::Result<()> {
+    let file = vfs::create_file(&format!("{}/{}", dir, INDEX_FILE), Some(5))?;
+    file.write(&serde_json::to_vec(index)?)?;
+    Ok(())
+}
+
+fn chunk_path(dir: &str, file: u64) -> String {
+    format!("{}/{}.json", dir, file)
+}
+
+fn read_chunk(dir: &str, info: &ChunkInfo) -> anyhow::Result<Vec<Rumor>> {
+    let file = vfs::open_file(&chunk_path(dir, info.file.unwrap_or(0)), false, Some(5))?;
+    Ok(serde_json::from_slice(&file.read()?)?)
+}
+
+fn write_chunk(dir: &str, file: u64, chunk: &[Rumor]) -> anyhow::Result<()> {
+    let file = vfs::create_file(&chunk_path(dir, file), Some(5))?;
+    file.write(&serde_json::to_vec(chunk)?)?;
+    Ok(())
+}
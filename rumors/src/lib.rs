@@ -1,8 +1,23 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 use dartfrog_lib::*;
 use hyperware_process_lib::{call_init, http::server, Address, println};
 use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+use archive::RetentionSettings;
+use bans::{new_ban, BanEntry, BanKind, BanList};
+use limits::{RumorLimiter, RumorLimits};
+use timers::handle_timers;
+
+mod archive;
+#[path = "../../shared/bans.rs"]
+mod bans;
+mod limits;
+#[path = "../../shared/pseudonym.rs"]
+mod pseudonym;
+#[path = "../../shared/timers.rs"]
+mod timers;
+
 wit_bindgen::generate!({
     path: "target/wit",
     world: "process-v1",
@@ -28,12 +43,14 @@ pub enum AppRequest {
 #[derive(Debug, Clone)]
 pub struct AppState {
     pub provider: AppProviderState,
+    pub next_timer: u64,
 }
 
 impl AppState {
     pub fn new(our: &Address) -> Self {
         AppState {
             provider: AppProviderState::new(our),
+            next_timer: 0,
         }
     }
 }
@@ -45,7 +62,9 @@ impl AppServiceState for AppService {
         }
     }
     fn init(&mut self, our: &Address, service: &Service) -> anyhow::Result<()> {
-        default_load_service::<Self>(our, &service.id.to_string(), self)
+        default_load_service::<Self>(our, &service.id.to_string(), self)?;
+        self.rumors.upgrade(our);
+        self.save_if_dirty(our, service)
     }
 
     fn save(&mut self, our: &Address, service: &Service) -> anyhow::Result<()> {
@@ -53,18 +72,37 @@ impl AppServiceState for AppService {
     }
 
     fn handle_subscribe(&mut self, subscriber_node: String, our: &Address, service: &Service) -> anyhow::Result<()> {
-        self.rumors.handle_subscribe(subscriber_node, our, service)?;
-        self.save(our, service)?;
-        Ok(())
+        let result = self.rumors.handle_subscribe(subscriber_node, our, service);
+        self.save_if_dirty(our, service)?;
+        result
     }
 
     fn handle_request(&mut self, from: String, req: String, our: &Address, service: &Service) -> anyhow::Result<()> {
         let request = serde_json::from_str::<AppRequest>(&req)?;
-        match request {
+        let result = match request {
             AppRequest::Rumors(rumors_request) => {
                 self.rumors.handle_request(from, rumors_request, our, service)
             }
+        };
+        self.save_if_dirty(our, service)?;
+        result
+    }
+}
+
+impl AppService {
+    // Rumors and bans expire without anyone using the service
+    fn handle_timer(&mut self, our: &Address, service: &Service) -> anyhow::Result<()> {
+        let result = self.rumors.handle_timer(our, service);
+        self.save_if_dirty(our, service)?;
+        result
+    }
+
+    // Runs even when a request failed partway, so whatever it already changed is kept
+    fn save_if_dirty(&mut self, our: &Address, service: &Service) -> anyhow::Result<()> {
+        if self.rumors.take_dirty() {
+            self.save(our, service)?;
         }
+        Ok(())
     }
 }
 
@@ -73,20 +111,89 @@ pub enum RumorsUpdate {
     Rumors(Vec<Rumor>),
     NewRumor(Rumor),
     BannedUsers(Vec<String>),
+    MutedUsers(Vec<String>),
     DeletedRumor(u64),
     RumorAuthor {
         rumor_id: u64,
         author: String,
     },
+    Bans(Vec<BanEntry>),
+    BanLookup {
+        user: String,
+        ban: Option<BanEntry>,
+    },
+    OlderRumors {
+        rumors: Vec<Rumor>,
+        has_more: bool,
+    },
+    Retention(RetentionSettings),
+    RetentionRejected { reason: String },
+    AnonymityMode(AnonymityMode),
+    RumorAuthorUnavailable { rumor_id: u64 },
+    RumorRejected { reason: String },
+    Limits(RumorLimits),
+    LimitsRejected { reason: String },
+    NewReply {
+        rumor_id: u64,
+        reply: RumorReply,
+    },
+    ReactionCount {
+        rumor_id: u64,
+        emoji: String,
+        count: u32,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum RumorsRequest {
-    CreateNewRumor(String),
-    BanUser { user: String },
+    CreateNewRumor { text: String, lifetime: Option<RumorLifetime> },
+    BanUser { user: String, reason: Option<String>, duration: Option<u64> },
+    MuteUser { user: String, reason: Option<String>, duration: Option<u64> },
     UnbanUser { user: String },
+    GetBan { user: String },
+    ExportBans,
+    ImportBans { bans: Vec<BanEntry> },
+    // also accepts reply ids, which share the rumor id space
     DeleteRumor { rumor_id: u64 },
     GetRumorAuthor { rumor_id: u64 },
+    CreateReply { rumor_id: u64, text: String },
+    React { rumor_id: u64, emoji: String },
+    Unreact { rumor_id: u64, emoji: String },
+    // `before` is the id of the oldest rumor the client already has
+    GetOlderRumors { before: u64, limit: usize },
+    SetRetention(RetentionSettings),
+    GetRetention,
+    SetAnonymityMode(AnonymityMode),
+    SetLimits(RumorLimits),
+    GetLimits,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RumorLifetime {
+    Hour,
+    Day,
+    Week,
+}
+
+impl RumorLifetime {
+    fn seconds(&self) -> u64 {
+        match self {
+            RumorLifetime::Hour => 60 * 60,
+            RumorLifetime::Day => 24 * 60 * 60,
+            RumorLifetime::Week => 7 * 24 * 60 * 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AnonymityMode {
+    // authors are stored by node name and the host can look them up
+    HostVisible,
+    // authors are stored only as a salted hash, which is enough for bans and
+    // one reaction per author. This keeps node names out of the saved state and
+    // the archive, not away from the host: the host sees which node sends each
+    // request, and holding the salt it can hash any node name it suspects.
+    Strong,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -95,25 +202,195 @@ pub struct Rumor {
     source: Option<String>,
     time: u64,
     text: String,
+    // Everything below is missing from rumors saved or archived by older
+    // versions, so each field has to default
+    // shown in place of the author, stable for one author within this service
+    #[serde(default)]
+    pseudonym: Option<String>,
+    // when the rumor deletes itself, replies and all
+    #[serde(default)]
+    expires_at: Option<u64>,
+    #[serde(default)]
+    replies: Vec<RumorReply>,
+    // counts only, who reacted is never sent out
+    #[serde(default)]
+    reactions: HashMap<String, u32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RumorReply {
+    id: u64,
+    source: Option<String>,
+    time: u64,
+    text: String,
+    #[serde(default)]
+    pseudonym: Option<String>,
+}
+
+impl Rumor {
+    fn anonymized(&self) -> Rumor {
+        Rumor {
+            id: self.id,
+            source: None,
+            text: self.text.clone(),
+            time: self.time,
+            pseudonym: self.pseudonym.clone(),
+            expires_at: self.expires_at,
+            replies: self.replies.iter().map(RumorReply::anonymized).collect(),
+            reactions: self.reactions.clone(),
+        }
+    }
+
+    fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    fn contains(&self, id: u64) -> bool {
+        self.id == id || self.replies.iter().any(|reply| reply.id == id)
+    }
+
+    fn author_of(&self, id: u64) -> Option<&String> {
+        if self.id == id {
+            return self.source.as_ref();
+        }
+        self.replies.iter()
+            .find(|reply| reply.id == id)
+            .and_then(|reply| reply.source.as_ref())
+    }
+
+    fn map_sources(&mut self, f: &impl Fn(&str) -> String) {
+        self.source = self.source.as_deref().map(f);
+        for reply in self.replies.iter_mut() {
+            reply.source = reply.source.as_deref().map(f);
+        }
+    }
+
+    fn remove_reply(&mut self, id: u64) -> bool {
+        let before = self.replies.len();
+        self.replies.retain(|reply| reply.id != id);
+        self.replies.len() != before
+    }
+}
+
+impl RumorReply {
+    fn anonymized(&self) -> RumorReply {
+        RumorReply {
+            id: self.id,
+            source: None,
+            text: self.text.clone(),
+            time: self.time,
+            pseudonym: self.pseudonym.clone(),
+        }
+    }
+}
+
+// Fields missing from older saves take their value from new()
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct RumorsServiceState {
     pub rumors: Vec<Rumor>,
-    pub banned_users: HashSet<String>,
+    pub bans: BanList,
     pub next_rumor_id: u64,
+    pub retention: RetentionSettings,
+    // expiry times of archived rumors that still have to be purged
+    pub expiring_archive: HashMap<u64, u64>,
+    // rumor id -> emoji -> nodes that reacted, only kept while the rumor is live
+    pub reactors: HashMap<u64, HashMap<String, HashSet<String>>>,
+    pub anonymity: AnonymityMode,
+    // never sent anywhere, it only exists inside this process
+    pub author_salt: [u8; 32],
+    pub pseudonym_secret: [u8; 32],
+    pub limits: RumorLimits,
+    #[serde(skip)]
+    pub limiter: RumorLimiter,
+    // set by anything that changes saved state, cleared once it's been saved
+    #[serde(skip)]
+    pub dirty: bool,
+    // the plain ban list saved before timed bans, moved into bans on load
+    #[serde(rename = "banned_users", skip_serializing)]
+    legacy_banned_users: HashSet<String>,
+}
+
+impl Default for RumorsServiceState {
+    fn default() -> Self {
+        RumorsServiceState::new()
+    }
 }
 
 impl RumorsServiceState {
     fn new() -> Self {
         RumorsServiceState {
             rumors: vec!(),
-            banned_users: HashSet::new(),
+            bans: BanList::default(),
             next_rumor_id: 1,
+            retention: RetentionSettings::default(),
+            expiring_archive: HashMap::new(),
+            reactors: HashMap::new(),
+            anonymity: AnonymityMode::HostVisible,
+            author_salt: rand::random(),
+            pseudonym_secret: rand::random(),
+            limits: RumorLimits::default(),
+            limiter: RumorLimiter::default(),
+            // a fresh service has secrets worth keeping before anything else happens
+            dirty: true,
+            legacy_banned_users: HashSet::new(),
+        }
+    }
+
+    // Brings state saved by older versions up to date
+    fn upgrade(&mut self, our: &Address) {
+        let now = get_now();
+        for user in std::mem::take(&mut self.legacy_banned_users) {
+            self.bans.insert(new_ban(our.node.clone(), user, BanKind::Ban, None, None, now));
+            self.dirty = true;
         }
     }
 
+    fn take_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.dirty)
+    }
+
+    // What gets stored as a rumor's source and used to key reactions
+    fn author_key(&self, node: &str) -> String {
+        match self.anonymity {
+            AnonymityMode::HostVisible => node.to_string(),
+            AnonymityMode::Strong => salted_author(&self.author_salt, node),
+        }
+    }
+
+    // Taken from the node rather than the author key, so it survives a mode change
+    fn pseudonym_for(&self, node: &str, service: &Service) -> String {
+        pseudonym::pseudonym(&self.pseudonym_secret, &service.id.to_string(), node)
+    }
+
+    // Bans can name a node, or an author key taken from a strong-mode rumor.
+    // Both are checked so bans carry over when the mode changes.
+    fn is_restricted(&self, node: &str, now: u64) -> bool {
+        self.bans.is_restricted(node, now)
+            || self.bans.is_restricted(&salted_author(&self.author_salt, node), now)
+    }
+
+    fn is_banned(&self, node: &str, now: u64) -> bool {
+        self.bans.is_banned(node, now)
+            || self.bans.is_banned(&salted_author(&self.author_salt, node), now)
+    }
+
     fn handle_subscribe(&mut self, subscriber_node: String, our: &Address, service: &Service) -> anyhow::Result<()> {
+        self.purge_expired(get_now(), our, service)?;
+        if self.bans.prune_expired(get_now()) {
+            self.dirty = true;
+            self.send_banned_users_update(our, service)?;
+        }
+        let banned_upd = RumorsUpdate::BannedUsers(self.bans.banned_users());
+        update_subscriber(AppUpdate::Rumors(banned_upd), &subscriber_node, our, service)?;
+        let muted_upd = RumorsUpdate::MutedUsers(self.bans.muted_users());
+        update_subscriber(AppUpdate::Rumors(muted_upd), &subscriber_node, our, service)?;
+        let mode_upd = RumorsUpdate::AnonymityMode(self.anonymity);
+        update_subscriber(AppUpdate::Rumors(mode_upd), &subscriber_node, our, service)?;
+        if self.is_banned(&subscriber_node, get_now()) {
+            return Ok(());
+        }
+
         let rumors: Vec<Rumor> = if subscriber_node == our.node {
             // Send full rumors (including source) to the current node
             self.rumors.iter().rev().take(64).cloned().collect()
@@ -122,72 +399,109 @@ impl RumorsServiceState {
             self.rumors.iter()
                 .rev()
                 .take(64)
-                .map(|rumor| Rumor {
-                    id: rumor.id,
-                    source: None,
-                    text: rumor.text.clone(),
-                    time: rumor.time,
-                })
+                .map(Rumor::anonymized)
                 .collect()
         };
         
         let upd = RumorsUpdate::Rumors(rumors);
         update_subscriber(AppUpdate::Rumors(upd), &subscriber_node, our, service)?;
-        let banned_upd = RumorsUpdate::BannedUsers(self.banned_users.iter().cloned().collect());
-        update_subscriber(AppUpdate::Rumors(banned_upd), &subscriber_node, our, service)?;
+        if subscriber_node == our.node {
+            update_subscriber(AppUpdate::Rumors(RumorsUpdate::Bans(self.bans.entries())), &subscriber_node, our, service)?;
+            update_subscriber(AppUpdate::Rumors(RumorsUpdate::Retention(self.retention.clone())), &subscriber_node, our, service)?;
+            update_subscriber(AppUpdate::Rumors(RumorsUpdate::Limits(self.limits.clone())), &subscriber_node, our, service)?;
+        }
         Ok(())
     }
 
+    fn handle_timer(&mut self, our: &Address, service: &Service) -> anyhow::Result<()> {
+        let now = get_now();
+        if self.bans.prune_expired(now) {
+            self.dirty = true;
+            self.send_banned_users_update(our, service)?;
+        }
+        self.purge_expired(now, our, service)?;
+        self.apply_retention(now, our, service)
+    }
+
     fn handle_request(&mut self, from: String, req: RumorsRequest, our: &Address, service: &Service) -> anyhow::Result<()> {
+        let now = get_now();
+        // the timer may not have run since the last request
+        self.handle_timer(our, service)?;
         match req {
-            RumorsRequest::CreateNewRumor(text) => {
-                if !self.banned_users.contains(&from) {
-                    let new_rumor = Rumor {
-                        id: self.next_rumor_id,
-                        source: Some(from),
-                        text: text.chars().take(2000).collect(),
-                        time: get_now(),
-                    };
-                    self.rumors.push(new_rumor.clone());
-                    self.next_rumor_id += 1;
-                    
+            RumorsRequest::CreateNewRumor { text, lifetime } => {
+                // both bans and mutes keep a node from posting
+                if !self.is_restricted(&from, now) {
+                    let text: String = text.chars().take(MAX_RUMOR_LENGTH).collect();
+                    if let Err(reason) = self.screen_rumor(&from, &text, None, now, our) {
+                        let upd = RumorsUpdate::RumorRejected { reason };
+                        return update_subscriber(AppUpdate::Rumors(upd), &from, our, service);
+                    }
+                    let source = self.author_key(&from);
+                    let pseudonym = self.pseudonym_for(&from, service);
+                    let new_rumor = self.add_rumor(source, pseudonym, text, lifetime, now);
+
                     // Create an anonymized version of the rumor for the update
-                    let anonymized_rumor = Rumor {
-                        id: new_rumor.id,
-                        source: None,
-                        text: new_rumor.text,
-                        time: new_rumor.time,
-                    };
-                    
-                    let upd = RumorsUpdate::NewRumor(anonymized_rumor);
+                    let upd = RumorsUpdate::NewRumor(new_rumor.anonymized());
                     update_subscribers(AppUpdate::Rumors(upd), our, service)?;
+                    self.apply_retention(now, our, service)?;
                 }
             }
-            RumorsRequest::BanUser { user } => {
-                if from == our.node {
-                    self.banned_users.insert(user);
+            RumorsRequest::BanUser { user, reason, duration } => {
+                if from == our.node && user != our.node {
+                    self.restrict(new_ban(from, user, BanKind::Ban, reason, duration, now));
+                    self.send_banned_users_update(our, service)?;
+                }
+            }
+            RumorsRequest::MuteUser { user, reason, duration } => {
+                if from == our.node && user != our.node {
+                    self.restrict(new_ban(from, user, BanKind::Mute, reason, duration, now));
                     self.send_banned_users_update(our, service)?;
                 }
             }
             RumorsRequest::UnbanUser { user } => {
                 if from == our.node {
-                    self.banned_users.remove(&user);
+                    self.unrestrict(&user);
+                    self.send_banned_users_update(our, service)?;
+                }
+            }
+            RumorsRequest::GetBan { user } => {
+                if from == our.node || from == user {
+                    let ban = self.bans.get(&user, now).cloned();
+                    update_subscriber(AppUpdate::Rumors(RumorsUpdate::BanLookup { user, ban }), &from, our, service)?;
+                }
+            }
+            RumorsRequest::ExportBans => {
+                if from == our.node {
+                    update_subscriber(AppUpdate::Rumors(RumorsUpdate::Bans(self.bans.entries())), &from, our, service)?;
+                }
+            }
+            RumorsRequest::ImportBans { bans } => {
+                if from == our.node {
+                    let bans = bans.into_iter().filter(|entry| entry.user != our.node).collect();
+                    self.import_bans(bans, now);
                     self.send_banned_users_update(our, service)?;
                 }
             }
             RumorsRequest::DeleteRumor { rumor_id } => {
                 if from == our.node {
-                    if let Some(index) = self.rumors.iter().position(|r| r.id == rumor_id) {
-                        self.rumors.remove(index);
+                    let deleted = self.remove_live(rumor_id) || archive::remove(our, service, rumor_id)?;
+                    if deleted {
                         let delete_update = RumorsUpdate::DeletedRumor(rumor_id);
                         update_subscribers(AppUpdate::Rumors(delete_update), our, service)?;
                     }
                 }
             }
             RumorsRequest::GetRumorAuthor { rumor_id } => {
-                if from == our.node {
-                    if let Some(rumor) = self.rumors.iter().find(|r| r.id == rumor_id) {
-                        if let Some(author) = &rumor.source {
+                if from == our.node && self.anonymity == AnonymityMode::Strong {
+                    let upd = RumorsUpdate::RumorAuthorUnavailable { rumor_id };
+                    update_subscriber(AppUpdate::Rumors(upd), &from, our, service)?;
+                } else if from == our.node {
+                    let rumor = match self.rumors.iter().find(|r| r.contains(rumor_id)) {
+                        Some(rumor) => Some(rumor.clone()),
+                        None => archive::find(our, service, rumor_id)?,
+                    };
+                    if let Some(rumor) = rumor {
+                        if let Some(author) = rumor.author_of(rumor_id) {
                             let author_update = RumorsUpdate::RumorAuthor {
                                 rumor_id,
                                 author: author.clone(),
@@ -197,16 +511,363 @@ impl RumorsServiceState {
                     }
                 }
             }
+            RumorsRequest::CreateReply { rumor_id, text } => {
+                if self.is_restricted(&from, now) {
+                    return Ok(());
+                }
+                let text: String = text.chars().take(MAX_RUMOR_LENGTH).collect();
+                if let Err(reason) = self.screen_rumor(&from, &text, Some(rumor_id), now, our) {
+                    let upd = RumorsUpdate::RumorRejected { reason };
+                    return update_subscriber(AppUpdate::Rumors(upd), &from, our, service);
+                }
+                let source = self.author_key(&from);
+                let pseudonym = self.pseudonym_for(&from, service);
+                let Some(reply) = self.add_reply(rumor_id, source, pseudonym, text, now) else {
+                    return Ok(());
+                };
+                let upd = RumorsUpdate::NewReply { rumor_id, reply: reply.anonymized() };
+                update_subscribers(AppUpdate::Rumors(upd), our, service)?;
+            }
+            RumorsRequest::React { rumor_id, emoji } => {
+                if self.is_restricted(&from, now) || !is_valid_reaction(&emoji) {
+                    return Ok(());
+                }
+                let reactor = self.author_key(&from);
+                let Some(count) = self.add_reaction(rumor_id, reactor, &emoji) else {
+                    return Ok(());
+                };
+                let upd = RumorsUpdate::ReactionCount { rumor_id, emoji, count };
+                update_subscribers(AppUpdate::Rumors(upd), our, service)?;
+            }
+            RumorsRequest::Unreact { rumor_id, emoji } => {
+                let reactor = self.author_key(&from);
+                let Some(count) = self.remove_reaction(rumor_id, &reactor, &emoji) else {
+                    return Ok(());
+                };
+                let upd = RumorsUpdate::ReactionCount { rumor_id, emoji, count };
+                update_subscribers(AppUpdate::Rumors(upd), our, service)?;
+            }
+            RumorsRequest::GetOlderRumors { before, limit } => {
+                if self.is_banned(&from, now) {
+                    return Ok(());
+                }
+                let limit = limit.clamp(1, MAX_OLDER_RUMORS_PAGE);
+                // one extra to tell whether there is another page
+                let mut rumors: Vec<Rumor> = self.rumors.iter()
+                    .rev()
+                    .filter(|rumor| rumor.id < before)
+                    .take(limit + 1)
+                    .cloned()
+                    .collect();
+                if rumors.len() <= limit {
+                    let oldest_live = self.rumors.first().map(|rumor| rumor.id).unwrap_or(u64::MAX);
+                    let archived = archive::older(our, service, before.min(oldest_live), limit + 1 - rumors.len())?;
+                    rumors.extend(archived);
+                }
+                let has_more = rumors.len() > limit;
+                rumors.truncate(limit);
+                if from != our.node {
+                    rumors = rumors.iter().map(Rumor::anonymized).collect();
+                }
+                let upd = RumorsUpdate::OlderRumors { rumors, has_more };
+                update_subscriber(AppUpdate::Rumors(upd), &from, our, service)?;
+            }
+            RumorsRequest::SetRetention(retention) => {
+                if from == our.node {
+                    if let Err(reason) = retention.validate() {
+                        let upd = RumorsUpdate::RetentionRejected { reason };
+                        return update_subscriber(AppUpdate::Rumors(upd), &from, our, service);
+                    }
+                    self.dirty = true;
+                    self.retention = retention;
+                    self.apply_retention(now, our, service)?;
+                    update_subscriber(AppUpdate::Rumors(RumorsUpdate::Retention(self.retention.clone())), &from, our, service)?;
+                }
+            }
+            RumorsRequest::SetAnonymityMode(mode) => {
+                // asking for strong mode again finishes a rewrite that failed partway
+                if from == our.node && (mode != self.anonymity || mode == AnonymityMode::Strong) {
+                    self.set_anonymity(mode);
+                    if mode == AnonymityMode::Strong {
+                        let salt = self.author_salt;
+                        archive::map_sources(our, service, &|node: &str| salted_author(&salt, node))?;
+                    }
+                    let upd = RumorsUpdate::AnonymityMode(mode);
+                    update_subscribers(AppUpdate::Rumors(upd), our, service)?;
+                }
+            }
+            RumorsRequest::SetLimits(limits) => {
+                if from == our.node {
+                    if let Err(reason) = limits.validate() {
+                        let upd = RumorsUpdate::LimitsRejected { reason };
+                        return update_subscriber(AppUpdate::Rumors(upd), &from, our, service);
+                    }
+                    self.dirty = true;
+                    self.limits = limits;
+                    update_subscriber(AppUpdate::Rumors(RumorsUpdate::Limits(self.limits.clone())), &from, our, service)?;
+                }
+            }
+            RumorsRequest::GetLimits => {
+                if from == our.node {
+                    update_subscriber(AppUpdate::Rumors(RumorsUpdate::Limits(self.limits.clone())), &from, our, service)?;
+                }
+            }
+            RumorsRequest::GetRetention => {
+                if from == our.node {
+                    update_subscriber(AppUpdate::Rumors(RumorsUpdate::Retention(self.retention.clone())), &from, our, service)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Repeat, cooldown and hourly cap checks, in that order so a rejected
+    // repeat doesn't use up the author's cooldown. The host is exempt.
+    fn screen_rumor(&mut self, from: &str, text: &str, reply_to: Option<u64>, now: u64, our: &Address) -> Result<(), String> {
+        if from == our.node {
+            return Ok(());
+        }
+        let lookback = self.limits.duplicate_lookback;
+        let similarity = self.limits.duplicate_similarity;
+        let is_repeat = match reply_to {
+            None => {
+                let recent = self.rumors.iter().rev().take(lookback).map(|rumor| rumor.text.as_str());
+                limits::is_near_duplicate(text, recent, similarity)
+            }
+            Some(rumor_id) => self.rumors.iter()
+                .find(|rumor| rumor.id == rumor_id)
+                .is_some_and(|rumor| {
+                    let recent = rumor.replies.iter().rev().take(lookback).map(|reply| reply.text.as_str());
+                    limits::is_near_duplicate(text, recent, similarity)
+                }),
+        };
+        if is_repeat {
+            return Err("this was already posted recently".to_string());
+        }
+        let author = self.author_key(from);
+        self.limiter.try_acquire(&author, now, &self.limits)
+    }
+
+    // The methods below change live state without touching the vfs or
+    // subscribers, and each marks the state dirty itself
+
+    fn add_rumor(&mut self, source: String, pseudonym: String, text: String, lifetime: Option<RumorLifetime>, now: u64) -> Rumor {
+        self.dirty = true;
+        let rumor = Rumor {
+            id: self.next_rumor_id,
+            source: Some(source),
+            text,
+            time: now,
+            pseudonym: Some(pseudonym),
+            expires_at: lifetime.map(|lifetime| now + lifetime.seconds()),
+            replies: Vec::new(),
+            reactions: HashMap::new(),
+        };
+        self.next_rumor_id += 1;
+        self.rumors.push(rumor.clone());
+        rumor
+    }
+
+    // Only live rumors take replies
+    fn add_reply(&mut self, rumor_id: u64, source: String, pseudonym: String, text: String, now: u64) -> Option<RumorReply> {
+        let rumor = self.rumors.iter_mut().find(|r| r.id == rumor_id)?;
+        if rumor.replies.len() >= MAX_REPLIES_PER_RUMOR {
+            return None;
+        }
+        self.dirty = true;
+        let reply = RumorReply {
+            id: self.next_rumor_id,
+            source: Some(source),
+            text,
+            time: now,
+            pseudonym: Some(pseudonym),
+        };
+        self.next_rumor_id += 1;
+        rumor.replies.push(reply.clone());
+        Some(reply)
+    }
+
+    // Returns the new count, or None if nothing changed
+    fn add_reaction(&mut self, rumor_id: u64, reactor: String, emoji: &str) -> Option<u32> {
+        let rumor = self.rumors.iter_mut().find(|r| r.id == rumor_id)?;
+        let reactors = self.reactors.entry(rumor_id).or_default();
+        if !reactors.contains_key(emoji) && reactors.len() >= MAX_REACTION_KINDS {
+            return None;
+        }
+        let nodes = reactors.entry(emoji.to_string()).or_default();
+        if nodes.contains(&reactor) {
+            return None;
+        }
+        self.dirty = true;
+        nodes.insert(reactor);
+        let count = nodes.len() as u32;
+        rumor.reactions.insert(emoji.to_string(), count);
+        Some(count)
+    }
+
+    fn remove_reaction(&mut self, rumor_id: u64, reactor: &str, emoji: &str) -> Option<u32> {
+        let rumor = self.rumors.iter_mut().find(|r| r.id == rumor_id)?;
+        let reactors = self.reactors.get_mut(&rumor_id)?;
+        let nodes = reactors.get_mut(emoji)?;
+        if !nodes.contains(reactor) {
+            return None;
+        }
+        self.dirty = true;
+        nodes.remove(reactor);
+        let count = nodes.len() as u32;
+        if count == 0 {
+            reactors.remove(emoji);
+            rumor.reactions.remove(emoji);
+        } else {
+            rumor.reactions.insert(emoji.to_string(), count);
+        }
+        Some(count)
+    }
+
+    // Removes a live rumor or reply, returning whether it was there
+    fn remove_live(&mut self, id: u64) -> bool {
+        if let Some(index) = self.rumors.iter().position(|r| r.id == id) {
+            self.dirty = true;
+            self.rumors.remove(index);
+            self.reactors.remove(&id);
+            return true;
+        }
+        let Some(rumor) = self.rumors.iter_mut().find(|r| r.contains(id)) else {
+            return false;
+        };
+        self.dirty = true;
+        rumor.remove_reply(id)
+    }
+
+    fn restrict(&mut self, entry: BanEntry) {
+        self.dirty = true;
+        self.bans.insert(entry);
+    }
+
+    fn unrestrict(&mut self, user: &str) {
+        self.dirty = true;
+        self.bans.remove(user);
+    }
+
+    fn import_bans(&mut self, bans: Vec<BanEntry>, now: u64) {
+        self.dirty = true;
+        self.bans.import(bans, now);
+    }
+
+    // Entering strong mode replaces every live node name with its author key.
+    // The caller rewrites the archive the same way.
+    fn set_anonymity(&mut self, mode: AnonymityMode) {
+        self.dirty = true;
+        self.anonymity = mode;
+        if mode != AnonymityMode::Strong {
+            return;
+        }
+        let salt = self.author_salt;
+        let to_key = |node: &str| salted_author(&salt, node);
+        for rumor in self.rumors.iter_mut() {
+            rumor.map_sources(&to_key);
+        }
+        for reactors in self.reactors.values_mut() {
+            for nodes in reactors.values_mut() {
+                *nodes = nodes.iter().map(|node| to_key(node.as_str())).collect();
+            }
+        }
+    }
+
+    // Moves rumors past the retention limits out of the live state and into the archive.
+    // Clients keep what they already have and page through older rumors on request.
+    fn apply_retention(&mut self, now: u64, our: &Address, service: &Service) -> anyhow::Result<()> {
+        let excess = self.retention.excess(&self.rumors, now);
+        if excess == 0 {
+            return Ok(());
+        }
+        // only taken out of the live state once the archive has them
+        archive::append(our, service, self.rumors[..excess].to_vec(), self.retention.max_archived)?;
+        self.dirty = true;
+        let archived: Vec<Rumor> = self.rumors.drain(..excess).collect();
+        // archived rumors keep their reaction counts but take no new reactions
+        for rumor in &archived {
+            self.reactors.remove(&rumor.id);
+            if let Some(expires_at) = rumor.expires_at {
+                self.expiring_archive.insert(rumor.id, expires_at);
+            }
+        }
+        Ok(())
+    }
+
+    // Expired rumors are deleted for good, whether still live or already archived.
+    // Runs on the timer and before every request.
+    fn purge_expired(&mut self, now: u64, our: &Address, service: &Service) -> anyhow::Result<()> {
+        let mut expired: Vec<u64> = self.rumors.iter()
+            .filter(|rumor| rumor.is_expired(now))
+            .map(|rumor| rumor.id)
+            .collect();
+        if !expired.is_empty() {
+            self.dirty = true;
+        }
+        self.rumors.retain(|rumor| !rumor.is_expired(now));
+        for rumor_id in &expired {
+            self.reactors.remove(rumor_id);
+        }
+
+        let archived: Vec<u64> = self.expiring_archive.iter()
+            .filter(|(_, &expires_at)| expires_at <= now)
+            .map(|(&rumor_id, _)| rumor_id)
+            .collect();
+        for rumor_id in archived {
+            self.dirty = true;
+            self.expiring_archive.remove(&rumor_id);
+            if archive::remove(our, service, rumor_id)? {
+                expired.push(rumor_id);
+            }
+        }
+
+        for rumor_id in expired {
+            update_subscribers(AppUpdate::Rumors(RumorsUpdate::DeletedRumor(rumor_id)), our, service)?;
         }
         Ok(())
     }
 
     fn send_banned_users_update(&self, our: &Address, service: &Service) -> anyhow::Result<()> {
-        let banned_users_update = RumorsUpdate::BannedUsers(self.banned_users.iter().cloned().collect());
-        update_subscribers(AppUpdate::Rumors(banned_users_update), our, service)
+        let banned_users_update = RumorsUpdate::BannedUsers(self.bans.banned_users());
+        update_subscribers(AppUpdate::Rumors(banned_users_update), our, service)?;
+        let muted_users_update = RumorsUpdate::MutedUsers(self.bans.muted_users());
+        update_subscribers(AppUpdate::Rumors(muted_users_update), our, service)?;
+        update_subscriber(AppUpdate::Rumors(RumorsUpdate::Bans(self.bans.entries())), &our.node, our, service)
     }
 }
 
+const MAX_OLDER_RUMORS_PAGE: usize = 64;
+const MAX_RUMOR_LENGTH: usize = 2000;
+const MAX_REPLIES_PER_RUMOR: usize = 200;
+const MAX_REACTION_KINDS: usize = 20;
+const MAX_REACTION_LENGTH: usize = 32;
+
+// A stable per-service stand-in for a node. Already-salted keys are left
+// alone so switching modes back and forth never double-hashes an author.
+fn salted_author(salt: &[u8; 32], node: &str) -> String {
+    if node.starts_with(AUTHOR_KEY_PREFIX) {
+        return node.to_string();
+    }
+    let mut hasher = Sha256::new();
+    hasher.update(salt);
+    hasher.update(node.as_bytes());
+    let digest = hasher.finalize();
+    let hex: String = digest.iter().take(8).map(|byte| format!("{:02x}", byte)).collect();
+    format!("{}{}", AUTHOR_KEY_PREFIX, hex)
+}
+
+const AUTHOR_KEY_PREFIX: &str = "anon:";
+
+// Reactions are meant to be emoji, so plain ascii text is turned away
+fn is_valid_reaction(emoji: &str) -> bool {
+    !emoji.is_empty()
+        && emoji.len() <= MAX_REACTION_LENGTH
+        && !emoji.chars().all(|c| c.is_ascii())
+        && !emoji.chars().any(|c| c.is_whitespace() || c.is_control())
+}
+
+
 call_init!(init);
 fn init(our: Address) {
     let mut state = AppState::new(&our);
@@ -229,6 +890,7 @@ fn init(our: Address) {
         .expect("failed to bind ws");
 
     loop {
+        handle_timers(&our, &mut state);
         match provider_handle_message(&our, &mut state.provider) {
             Ok(()) => {}
             Err(e) => {
@@ -236,4 +898,189 @@ fn init(our: Address) {
             }
         };
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn our() -> Address {
+        Address::new("host.os", ("rumors", "dartfrog", "gliderlabs.os"))
+    }
+
+    // Loads saved json the way init does, after default_load_service
+    fn load(saved: &str) -> RumorsServiceState {
+        let mut state: RumorsServiceState = serde_json::from_str(saved).unwrap();
+        state.upgrade(&our());
+        state
+    }
+
+    // The same json that default_save_service writes, loaded back through init's path
+    fn reload(state: &RumorsServiceState) -> RumorsServiceState {
+        load(&serde_json::to_string(state).unwrap())
+    }
+
+    // Sets serialize in hash order, so reactors are compared as values and
+    // everything else as json
+    fn assert_same(loaded: &RumorsServiceState, state: &RumorsServiceState) {
+        assert_eq!(loaded.reactors, state.reactors);
+        let strip = |state: &RumorsServiceState| {
+            let mut value = serde_json::to_value(state).unwrap();
+            value.as_object_mut().unwrap().remove("reactors");
+            value
+        };
+        assert_eq!(strip(loaded), strip(state));
+    }
+
+    // A change has to mark the state dirty, and has to survive a save and load
+    fn assert_saved(state: &mut RumorsServiceState) -> RumorsServiceState {
+        assert!(state.take_dirty(), "change was not marked dirty");
+        let loaded = reload(state);
+        assert_same(&loaded, state);
+        loaded
+    }
+
+    fn clean_state() -> RumorsServiceState {
+        let mut state = RumorsServiceState::new();
+        state.take_dirty();
+        state
+    }
+
+    fn with_rumor() -> (RumorsServiceState, u64) {
+        let mut state = clean_state();
+        let rumor = state.add_rumor("a.os".to_string(), "Anon Frog #00000001".to_string(), "hi".to_string(), None, 10);
+        state.take_dirty();
+        (state, rumor.id)
+    }
+
+    #[test]
+    fn new_state_is_saved() {
+        let mut state = RumorsServiceState::new();
+        let loaded = assert_saved(&mut state);
+        assert_eq!(loaded.author_salt, state.author_salt);
+        assert_eq!(loaded.pseudonym_secret, state.pseudonym_secret);
+    }
+
+    #[test]
+    fn add_rumor_is_saved() {
+        let mut state = clean_state();
+        let rumor = state.add_rumor("a.os".to_string(), "Anon Frog #00000001".to_string(), "hi".to_string(), Some(RumorLifetime::Hour), 10);
+        let loaded = assert_saved(&mut state);
+        assert_eq!(loaded.rumors.len(), 1);
+        assert_eq!(loaded.rumors[0].expires_at, Some(10 + 60 * 60));
+        assert_eq!(loaded.next_rumor_id, rumor.id + 1);
+    }
+
+    #[test]
+    fn add_reply_is_saved() {
+        let (mut state, rumor_id) = with_rumor();
+        assert!(state.add_reply(rumor_id, "b.os".to_string(), "Anon Frog #00000002".to_string(), "yes".to_string(), 11).is_some());
+        let loaded = assert_saved(&mut state);
+        assert_eq!(loaded.rumors[0].replies.len(), 1);
+    }
+
+    #[test]
+    fn reactions_are_saved() {
+        let (mut state, rumor_id) = with_rumor();
+        assert_eq!(state.add_reaction(rumor_id, "b.os".to_string(), "🐸"), Some(1));
+        let loaded = assert_saved(&mut state);
+        assert_eq!(loaded.rumors[0].reactions.get("🐸"), Some(&1));
+        assert_eq!(state.remove_reaction(rumor_id, "b.os", "🐸"), Some(0));
+        let loaded = assert_saved(&mut state);
+        assert!(loaded.rumors[0].reactions.is_empty());
+        assert!(loaded.reactors.get(&rumor_id).is_some_and(|reactors| reactors.is_empty()));
+    }
+
+    #[test]
+    fn remove_live_is_saved() {
+        let (mut state, rumor_id) = with_rumor();
+        let reply = state.add_reply(rumor_id, "b.os".to_string(), "Anon Frog #00000002".to_string(), "yes".to_string(), 11).unwrap();
+        state.take_dirty();
+        assert!(state.remove_live(reply.id));
+        let loaded = assert_saved(&mut state);
+        assert!(loaded.rumors[0].replies.is_empty());
+        assert!(state.remove_live(rumor_id));
+        let loaded = assert_saved(&mut state);
+        assert!(loaded.rumors.is_empty());
+    }
+
+    #[test]
+    fn bans_are_saved() {
+        let mut state = clean_state();
+        state.restrict(new_ban("host.os".to_string(), "b.os".to_string(), BanKind::Mute, None, Some(60), 10));
+        let loaded = assert_saved(&mut state);
+        assert!(loaded.bans.is_restricted("b.os", 20));
+        state.unrestrict("b.os");
+        let loaded = assert_saved(&mut state);
+        assert!(!loaded.bans.is_restricted("b.os", 20));
+        state.import_bans(vec![new_ban("other.os".to_string(), "c.os".to_string(), BanKind::Ban, None, None, 10)], 20);
+        let loaded = assert_saved(&mut state);
+        assert!(loaded.bans.is_banned("c.os", 20));
+    }
+
+    #[test]
+    fn imported_bans_are_validated() {
+        let mut state = clean_state();
+        let mut long_reason = new_ban("other.os".to_string(), "d.os".to_string(), BanKind::Ban, None, None, 10);
+        long_reason.reason = Some(format!("spam\n{}", "x".repeat(bans::MAX_BAN_REASON_LENGTH * 2)));
+        let future = new_ban("other.os".to_string(), "e.os".to_string(), BanKind::Ban, None, None, 30);
+        let mut backwards = new_ban("other.os".to_string(), "f.os".to_string(), BanKind::Ban, None, None, 10);
+        backwards.expires_at = Some(5);
+        let unnamed = new_ban("other.os".to_string(), " ".to_string(), BanKind::Ban, None, None, 10);
+        state.import_bans(vec![long_reason, future, backwards, unnamed], 20);
+        let entries = state.bans.entries();
+        assert_eq!(entries.len(), 1);
+        let reason = entries[0].reason.clone().unwrap();
+        assert!(reason.starts_with("spam x"));
+        assert_eq!(reason.chars().count(), bans::MAX_BAN_REASON_LENGTH);
+    }
+
+    #[test]
+    fn strong_mode_is_saved_with_stripped_authors() {
+        let (mut state, rumor_id) = with_rumor();
+        state.add_reaction(rumor_id, "b.os".to_string(), "🐸");
+        state.take_dirty();
+        state.set_anonymity(AnonymityMode::Strong);
+        let loaded = assert_saved(&mut state);
+        assert_eq!(loaded.anonymity, AnonymityMode::Strong);
+        let source = loaded.rumors[0].source.clone().unwrap();
+        assert_eq!(source, salted_author(&loaded.author_salt, "a.os"));
+        let reactors = &loaded.reactors[&rumor_id]["🐸"];
+        assert!(reactors.contains(&salted_author(&loaded.author_salt, "b.os")));
+    }
+
+    #[test]
+    fn refused_changes_stay_clean() {
+        let (mut state, rumor_id) = with_rumor();
+        assert!(state.add_reply(rumor_id + 100, "b.os".to_string(), String::new(), "no".to_string(), 11).is_none());
+        assert!(state.add_reaction(rumor_id + 100, "b.os".to_string(), "🐸").is_none());
+        assert!(state.remove_reaction(rumor_id, "b.os", "🐸").is_none());
+        assert!(!state.remove_live(rumor_id + 100));
+        assert!(!state.take_dirty());
+    }
+
+    #[test]
+    fn baseline_save_loads_and_upgrades() {
+        let saved = r#"{"rumors":[{"id":1,"source":"a.os","time":5,"text":"hi"}],"banned_users":["b.os"],"next_rumor_id":2}"#;
+        let mut state = load(saved);
+        let loaded = assert_saved(&mut state);
+        assert_eq!(loaded.rumors[0].text, "hi");
+        assert!(loaded.rumors[0].replies.is_empty());
+        assert!(loaded.bans.is_banned("b.os", 10));
+        assert_eq!(loaded.bans.entries()[0].issued_by, "host.os");
+        assert_eq!(loaded.next_rumor_id, 2);
+        // the salts made up on load are kept, or authors would change every restart
+        assert_eq!(reload(&loaded).author_salt, state.author_salt);
+        assert_eq!(loaded.pseudonym_secret, state.pseudonym_secret);
+    }
+
+    #[test]
+    fn plain_ban_list_save_loads_every_ban() {
+        let saved = r#"{"rumors":[],"banned_users":["b.os","c.os"],"next_rumor_id":1,"reactors":{},"anonymity":"HostVisible"}"#;
+        let mut state = load(saved);
+        let loaded = assert_saved(&mut state);
+        assert!(loaded.bans.is_banned("b.os", 10));
+        assert!(loaded.bans.is_banned("c.os", 10));
+        assert!(serde_json::to_value(&loaded).unwrap().get("banned_users").is_none());
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use serde::{Serialize, Deserialize};
+
+const HOUR: u64 = 3600;
+const SHINGLE_SIZE: usize = 3;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RumorLimits {
+    pub cooldown: u64, // seconds between posts from one author
+    pub max_per_hour: usize, // across the whole service
+    // how many recent rumors a new one is compared against
+    pub duplicate_lookback: usize,
+    // share of character trigrams two texts need in common to count as the same
+    pub duplicate_similarity: f64,
+}
+
+impl Default for RumorLimits {
+    fn default() -> Self {
+        RumorLimits {
+            cooldown: 30,
+            max_per_hour: 120,
+            duplicate_lookback: 200,
+            duplicate_similarity: 0.8,
+        }
+    }
+}
+
+impl RumorLimits {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_per_hour == 0 {
+            return Err("max_per_hour must be at least 1".to_string());
+        }
+        // at 0 every text counts as a duplicate of every other, so nothing could be posted
+        if !(self.duplicate_similarity > 0.0 && self.duplicate_similarity <= 1.0) {
+            return Err("duplicate_similarity must be above 0 and at most 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+// Recent posting times. Not persisted, a restart just forgets them.
+#[derive(Debug, Clone, Default)]
+pub struct RumorLimiter {
+    last_post: HashMap<String, u64>,
+    recent: VecDeque<u64>,
+}
+
+impl RumorLimiter {
+    // Checks both limits and records the post if it passes
+    pub fn try_acquire(&mut self, author: &str, now: u64, limits: &RumorLimits) -> Result<(), String> {
+        if let Some(last) = self.last_post.get(author) {
+            let wait = (last + limits.cooldown).saturating_sub(now);
+            if wait > 0 {
+                return Err(format!("please wait {} more seconds before posting again", wait));
+            }
+        }
+        while self.recent.front().is_some_and(|&time| time + HOUR <= now) {
+            self.recent.pop_front();
+        }
+        if self.recent.len() >= limits.max_per_hour {
+            return Err("this service has reached its limit of rumors for the hour, try again later".to_string());
+        }
+        self.last_post.insert(author.to_string(), now);
+        self.recent.push_back(now);
+        // authors past their cooldown have nothing left to enforce
+        self.last_post.retain(|_, last| *last + limits.cooldown > now);
+        Ok(())
+    }
+}
+
+// Whether `text` is close enough to any of `previous` to count as a repeat
+pub fn is_near_duplicate<'a>(text: &str, mut previous: impl Iterator<Item = &'a str>, similarity: f64) -> bool {
+    let normalized = normalize(text);
+    if normalized.is_empty() {
+        return false;
+    }
+    let own = shingles(&normalized);
+    previous.any(|other| {
+        let other = normalize(other);
+        if normalized == other {
+            return true;
+        }
+        let other = shingles(&other);
+        if own.is_empty() || other.is_empty() {
+            return false;
+        }
+        let shared = own.intersection(&other).count();
+        let total = own.union(&other).count();
+        shared as f64 / total as f64 >= similarity
+    })
+}
+
+// Lowercase words only, so case, punctuation and spacing tricks don't count as new text
+fn normalize(text: &str) -> String {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn shingles(text: &str) -> HashSet<Vec<char>> {
+    let chars: Vec<char> = text.chars().collect();
+    chars.windows(SHINGLE_SIZE).map(|window| window.to_vec()).collect()
+}
@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+
+use serde::{Serialize, Deserialize};
+
+// Shared by every service that keeps a ban list, each one includes this file
+// as its own bans module
+
+pub const MAX_BAN_REASON_LENGTH: usize = 300;
+// node names, and the pseudonyms anonymous authors are banned under
+pub const MAX_BAN_USER_LENGTH: usize = 256;
+
+// A ban removes a node from the service entirely, a mute leaves it read-only
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BanKind {
+    Ban,
+    Mute,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BanEntry {
+    pub user: String,
+    pub kind: BanKind,
+    pub reason: Option<String>,
+    pub issued_by: String,
+    pub issued_at: u64,
+    pub expires_at: Option<u64>,
+}
+
+// Reasons are shown to moderators as a single line
+pub fn new_ban(issued_by: String, user: String, kind: BanKind, reason: Option<String>, duration: Option<u64>, now: u64) -> BanEntry {
+    BanEntry {
+        user,
+        kind,
+        reason: reason
+            .map(|reason| reason.split_whitespace().collect::<Vec<_>>().join(" "))
+            .map(|reason| reason.chars().take(MAX_BAN_REASON_LENGTH).collect())
+            .filter(|reason: &String| !reason.is_empty()),
+        issued_by,
+        issued_at: now,
+        expires_at: duration.map(|duration| now.saturating_add(duration)),
+    }
+}
+
+impl BanEntry {
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BanList {
+    bans: HashMap<String, BanEntry>,
+}
+
+impl BanList {
+    pub fn insert(&mut self, entry: BanEntry) {
+        self.bans.insert(entry.user.clone(), entry);
+    }
+
+    pub fn remove(&mut self, user: &str) -> Option<BanEntry> {
+        self.bans.remove(user)
+    }
+
+    pub fn get(&self, user: &str, now: u64) -> Option<&BanEntry> {
+        self.bans.get(user).filter(|entry| !entry.is_expired(now))
+    }
+
+    pub fn is_banned(&self, user: &str, now: u64) -> bool {
+        self.get(user, now).is_some_and(|entry| entry.kind == BanKind::Ban)
+    }
+
+    // Both bans and mutes take away write access
+    pub fn is_restricted(&self, user: &str, now: u64) -> bool {
+        self.get(user, now).is_some()
+    }
+
+    // Returns whether anything expired, so callers know to broadcast the new list
+    pub fn prune_expired(&mut self, now: u64) -> bool {
+        let before = self.bans.len();
+        self.bans.retain(|_, entry| !entry.is_expired(now));
+        self.bans.len() != before
+    }
+
+    pub fn banned_users(&self) -> Vec<String> {
+        self.users_of_kind(BanKind::Ban)
+    }
+
+    pub fn muted_users(&self) -> Vec<String> {
+        self.users_of_kind(BanKind::Mute)
+    }
+
+    fn users_of_kind(&self, kind: BanKind) -> Vec<String> {
+        self.bans.values()
+            .filter(|entry| entry.kind == kind)
+            .map(|entry| entry.user.clone())
+            .collect()
+    }
+
+    pub fn entries(&self) -> Vec<BanEntry> {
+        self.bans.values().cloned().collect()
+    }
+
+    // Adds entries from another community's exported list. Entries are rebuilt
+    // the way local bans are, and malformed, future dated or expired ones are
+    // dropped. Existing local entries win over imported ones.
+    pub fn import(&mut self, entries: Vec<BanEntry>, now: u64) -> usize {
+        let mut imported = 0;
+        for entry in entries {
+            let Some(entry) = validate_import(entry, now) else {
+                continue;
+            };
+            if self.bans.contains_key(&entry.user) {
+                continue;
+            }
+            self.insert(entry);
+            imported += 1;
+        }
+        imported
+    }
+}
+
+fn validate_import(entry: BanEntry, now: u64) -> Option<BanEntry> {
+    let valid_name = |name: &str| !name.trim().is_empty() && name.len() <= MAX_BAN_USER_LENGTH;
+    if !valid_name(&entry.user) || !valid_name(&entry.issued_by) || entry.issued_at > now {
+        return None;
+    }
+    let duration = match entry.expires_at {
+        Some(expires_at) if expires_at <= entry.issued_at => return None,
+        Some(expires_at) => Some(expires_at - entry.issued_at),
+        None => None,
+    };
+    let entry = new_ban(entry.issued_by, entry.user, entry.kind, entry.reason, duration, entry.issued_at);
+    (!entry.is_expired(now)).then_some(entry)
+}
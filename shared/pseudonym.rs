@@ -0,0 +1,19 @@
+use sha2::{Digest, Sha256};
+
+// Shared by the forum and rumors, each one includes this file as its own
+// pseudonym module
+
+// A readable stand-in for an anonymous author, like "Anon Frog #4f2a91c0". It
+// is stable for an author within one service, and since the service id is mixed
+// in with the host's secret, the same node gets unrelated names elsewhere. The
+// suffix is 32 bits so two authors in one busy thread rarely share a name.
+pub fn pseudonym(secret: &[u8; 32], service_id: &str, author: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(secret);
+    hasher.update(service_id.as_bytes());
+    // keeps ("ab", "c") and ("a", "bc") from hashing the same
+    hasher.update([0]);
+    hasher.update(author.as_bytes());
+    let digest = hasher.finalize();
+    format!("Anon Frog #{:02x}{:02x}{:02x}{:02x}", digest[0], digest[1], digest[2], digest[3])
+}
@@ -0,0 +1,37 @@
+use dartfrog_lib::Service;
+use hyperware_process_lib::{timer, Address};
+
+use crate::{AppService, AppState};
+
+// Shared by every service process with timed work, each one includes this file
+// as its own timers module. The including crate provides AppState, with its
+// provider and next_timer, and AppService::handle_timer.
+
+// dartfrog_lib only calls into services on subscribes and requests, so timed
+// work runs from the process loop, which a timer wakes at least this often
+pub const TIMER_INTERVAL: u64 = 60; // seconds
+
+pub fn handle_timers(our: &Address, state: &mut AppState) {
+    let now = dartfrog_lib::get_now();
+    if now < state.next_timer {
+        return;
+    }
+    state.next_timer = now + TIMER_INTERVAL;
+    timer::set_timer(TIMER_INTERVAL * 1000, None);
+    for_each_service(our, state, "running timers", |app_service, service| {
+        app_service.handle_timer(our, service)
+    });
+}
+
+// The one place that walks ProviderState's services (its pub services map,
+// and each provider's pub state and service), so a change there lands here
+pub fn for_each_service<F>(our: &Address, state: &mut AppState, doing: &str, mut f: F)
+where
+    F: FnMut(&mut AppService, &Service) -> anyhow::Result<()>,
+{
+    for service_provider in state.provider.services.values_mut() {
+        if let Err(e) = f(&mut service_provider.state, &service_provider.service) {
+            println!("{} error {}: {:?}", our.process(), doing, e);
+        }
+    }
+}
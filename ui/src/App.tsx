@@ -16,7 +16,7 @@ import { BrowserRouter as Router, Route, Routes } from "react-router-dom";
 
 function App() {
 
-  const {setApi, closeApi, setIsClientConnected, setProfile, pokeHeartbeat, pokeRequestVersion, putMessageStoreMap, setMessageStoreMap, localFwdAllPeerRequests, setActivitySetting, peerMap, putPeerMap, localServices, setLocalServices } = useDartStore();
+  const {setApi, closeApi, setIsClientConnected, setProfile, pokeHeartbeat, pokeRequestVersion, putMessageStoreMap, setMessageStoreMap, localFwdAllPeerRequests, setActivitySetting, peerMap, putPeerMap, localServices, setLocalServices, handleDirectMessagesOutput, handleGroupMessagesOutput } = useDartStore();
 
   const [versionOutdated, setVersionOutdated] = useState(false);
 
@@ -90,6 +90,10 @@ function App() {
             setMessageStoreMap(newMessageStoreMap);
           } else if (data["MessageStore"]) {
             putMessageStoreMap(data["MessageStore"])
+          } else if (data["DirectMessages"]) {
+            handleDirectMessagesOutput(data["DirectMessages"])
+          } else if (data["GroupMessages"]) {
+            handleGroupMessagesOutput(data["GroupMessages"])
           } else if (data["RequestVersionResponse"]) {
             let [node, version] = data["RequestVersionResponse"]
             if (version !== DARTFROG_VERSION) {
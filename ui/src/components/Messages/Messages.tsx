@@ -10,7 +10,7 @@ export const hasUnreadHistory = (history: DirectMessage[]) => {
 };
 
 const Messages: React.FC = () => {
-    const {setCurrentPage, messageStoreMap, peerMap} = useDartStore();
+    const {setCurrentPage, messageStoreMap, peerMap, groups, groupInvites, requestCreateGroup, requestAcceptInvite, requestDeclineInvite} = useDartStore();
 
     useEffect(()=>{
         setCurrentPage('messages')
@@ -26,6 +26,17 @@ const Messages: React.FC = () => {
         navigate(`/messages/${inputValue}`)
     }, [inputValue]);
 
+    const [groupName, setGroupName] = useState('');
+    const [groupMembers, setGroupMembers] = useState('');
+
+    const handleCreateGroup = useCallback(() => {
+        const members = groupMembers.split(",").map(node => node.trim()).filter(node => node !== "");
+        if (members.length === 0) return;
+        requestCreateGroup(groupName.trim(), members);
+        setGroupName('');
+        setGroupMembers('');
+    }, [groupName, groupMembers]);
+
 
     const getLatestMessageContents = (history: DirectMessage[]) => {
         if (history.length === 0) return "";
@@ -89,6 +100,12 @@ const Messages: React.FC = () => {
         });
     }, [messageStoreMap, peerMap]);
 
+    const sortedGroups = React.useMemo(() => {
+        return Array.from(groups.values()).sort((a, b) =>
+            (getLatestTimestampUnformatted(b.history) ?? 0) - (getLatestTimestampUnformatted(a.history) ?? 0)
+        );
+    }, [groups]);
+
     return (
         <div
           style={{
@@ -119,6 +136,33 @@ const Messages: React.FC = () => {
                 />
                 <button onClick={handleSubmit}>new</button>
             </div>
+            <div
+              style={{
+                flexShrink: 0,
+                display: "flex",
+                flexDirection: "row",
+              }}
+            >
+                <input
+                  type="text"
+                  value={groupName}
+                  placeholder='group name'
+                  onChange={(e) => setGroupName(e.target.value)}
+                  style={{
+                    width:"30%",
+                  }}
+                />
+                <input
+                  type="text"
+                  value={groupMembers}
+                  placeholder='member.os, other-member.os'
+                  onChange={(e) => setGroupMembers(e.target.value)}
+                  style={{
+                    flexGrow:"1",
+                  }}
+                />
+                <button onClick={handleCreateGroup}>new group</button>
+            </div>
 
             <div
               style={{
@@ -129,7 +173,99 @@ const Messages: React.FC = () => {
                 overflowY: "auto",
               }}
             >
-              {sortedEntries.length === 0 ? (
+              {Array.from(groupInvites.values()).map((invite) => (
+                <div key={`invite:${invite.info.id}`}
+                  style={{
+                    display:"flex",
+                    flexDirection:"row",
+                    width:"100%",
+                    alignItems:"center",
+                    gap:"1rem",
+                    padding:"8px",
+                    overflowX:"hidden",
+                    flexShrink:"0",
+                  }}
+                >
+                    <div
+                      style={{
+                        display: "flex",
+                        flexDirection: "column",
+                        flexGrow: 1,
+                        minWidth: 0,
+                      }}
+                    >
+                        <span>{invite.info.name || "group"}</span>
+                        <span style={{ color: "gray", fontSize:"0.7rem"}}>
+                            invite from {invite.from}, {invite.info.members.length} members
+                        </span>
+                    </div>
+                    <button onClick={() => requestAcceptInvite(invite.info.id)}>join</button>
+                    <button onClick={() => requestDeclineInvite(invite.info.id)}>decline</button>
+                </div>
+              ))}
+              {sortedGroups.map((conversation) => (
+                <div key={conversation.id}
+                  style={{
+                    display:"flex",
+                    flexDirection:"row",
+                    width:"100%",
+                    cursor:"pointer",
+                    gap:"1rem",
+                    padding:"8px",
+                    overflowX:"hidden",
+                    flexShrink:"0",
+                  }}
+                  className="hover-dark-gray"
+                  onClick={()=>{
+                    navigate(`/messages/group/${encodeURIComponent(conversation.id)}`)
+                  }}
+                >
+                    {hasUnreadHistory(conversation.history) &&
+                      <div
+                        style={{
+                          display: "flex",
+                          flexDirection: "column",
+                          justifyContent: "center",
+                        }}
+                        className="name-color-blue"
+                      >
+                        ●
+                      </div>
+                    }
+                    <div
+                      style={{
+                        display: "flex",
+                        flexDirection: "column",
+                        justifyContent: "center",
+                        minWidth: 0,
+                      }}
+                    >
+                        <div
+                          style={{
+                            display: "flex",
+                            flexDirection: "row",
+                            gap: "1rem",
+                            alignItems: "center",
+                          }}
+                        >
+                            <span>{conversation.name || "group"}</span>
+                            <span style={{ color: "gray", fontSize:"0.7rem"}}>
+                                {conversation.members.length} members {getLatestTimestamp(conversation.history)}
+                            </span>
+                        </div>
+                        <div style={{
+                            color: "gray",
+                            whiteSpace: "nowrap",
+                            overflow: "hidden",
+                            textOverflow: "ellipsis",
+                            maxWidth: "100%"
+                        }}>
+                            {getLatestMessageContents(conversation.history)}
+                        </div>
+                    </div>
+                </div>
+              ))}
+              {sortedEntries.length === 0 && sortedGroups.length === 0 && groupInvites.size === 0 ? (
                 <div
                   style={{
                     display: "flex",
@@ -0,0 +1,199 @@
+import React, { useCallback, useEffect, useRef, useState } from 'react';
+import useDartStore from '../../store/dart';
+import { useNavigate, useParams } from 'react-router-dom';
+import { renderLoading } from '../Middle';
+import ProfilePicture from '../ProfilePicture';
+import { hasUnreadHistory } from './Messages';
+import { formatTimestamp, getPeerNameColor, maybeReplaceWithImage } from '@dartfrog/puddle';
+
+const MessagesGroup: React.FC = () => {
+    const {setCurrentPage, groups, groupInvites, peerMap, requestSendGroupMessage, requestInviteToGroup, requestLeaveGroup, clearUnreadGroup, requestAcceptInvite, requestDeclineInvite} = useDartStore();
+
+    const { conversationId } = useParams<{ conversationId: string }>();
+    const conversation = groups.get(conversationId);
+    const pendingInvite = groupInvites.get(conversationId);
+
+    const [messageText, setMessageText] = useState('');
+    const [inviteNode, setInviteNode] = useState('');
+
+    const messagesEndRef = useRef<HTMLDivElement | null>(null);
+
+    const navigate = useNavigate();
+
+    useEffect(()=>{
+        setCurrentPage('messages')
+    }, [])
+
+    useEffect(() => {
+        if (messagesEndRef.current) {
+            messagesEndRef.current.scrollIntoView({ block: 'end' });
+        }
+        if (conversation && hasUnreadHistory(conversation.history)) {
+            clearUnreadGroup(conversationId);
+        }
+    }, [conversation]);
+
+    const sendMessage = useCallback(() => {
+        if (!messageText) return;
+        requestSendGroupMessage(conversationId, maybeReplaceWithImage(messageText));
+        setMessageText('');
+    }, [messageText, conversationId]);
+
+    const invite = useCallback(() => {
+        if (!inviteNode.trim()) return;
+        requestInviteToGroup(conversationId, inviteNode.trim());
+        setInviteNode('');
+    }, [inviteNode, conversationId]);
+
+    if (!conversation && pendingInvite) {
+      // nothing from the group is shown until the invite is accepted
+      return (
+        <div
+          style={{
+            display: "flex",
+            flexDirection: "column",
+            justifyContent: "center",
+            alignItems: "center",
+            height: "100%",
+            gap: "1rem",
+            textAlign: "center",
+          }}
+        >
+            <div>
+                <span className={getPeerNameColor(peerMap.get(pendingInvite.from))}>{pendingInvite.from}</span>
+                {" "}added you to {pendingInvite.info.name || "a group"}
+            </div>
+            <div style={{ fontSize: "0.8rem", color: "gray" }}>
+                {pendingInvite.info.members.join(", ")}
+            </div>
+            <div style={{ display: "flex", flexDirection: "row", gap: "1rem" }}>
+                <button onClick={() => requestAcceptInvite(conversationId)}>join</button>
+                <button
+                  onClick={() => {
+                    requestDeclineInvite(conversationId);
+                    navigate("/messages");
+                  }}
+                >
+                    decline
+                </button>
+            </div>
+        </div>
+      );
+    }
+
+    if (!conversation) {
+      return renderLoading();
+    }
+
+    return (
+        <div
+          style={{
+            display: "flex",
+            flexDirection: "column",
+            flexGrow: "1",
+            maxHeight: "100%",
+            overflow: "hidden",
+          }}
+        >
+            {/* Header */}
+            <div
+              style={{
+                display: "flex",
+                flexDirection: "column",
+                gap: "0.3rem",
+                padding: "0.5rem 1rem",
+                boxShadow: "0 2px 4px rgba(0, 0, 0, 0.1)",
+              }}
+            >
+                <div style={{ display: "flex", flexDirection: "row", alignItems: "center", gap: "1rem" }}>
+                    <span
+                      style={{ cursor: "pointer", fontSize: "1.6rem", color: "gray" }}
+                      className="hover-dark-gray"
+                      onClick={() => navigate("/messages")}
+                    >
+                        ←
+                    </span>
+                    <span style={{ fontSize: "1.3rem", flexGrow: 1 }}>{conversation.name || "group"}</span>
+                    <button
+                      onClick={() => {
+                        requestLeaveGroup(conversationId);
+                        navigate("/messages");
+                      }}
+                    >
+                        leave
+                    </button>
+                </div>
+                <div style={{ fontSize: "0.8rem", color: "gray" }}>
+                    {conversation.members.join(", ")}
+                </div>
+                <div style={{ display: "flex", flexDirection: "row" }}>
+                    <input
+                      type="text"
+                      value={inviteNode}
+                      placeholder="node-to-invite.os"
+                      onChange={(e) => setInviteNode(e.target.value)}
+                      style={{ flexGrow: 1 }}
+                    />
+                    <button onClick={invite}>invite</button>
+                </div>
+            </div>
+
+            {/* Message history */}
+            <div style={{ flexGrow: 1, overflowY: "auto", overflowX: "hidden", width: "100%" }}>
+                {conversation.history.length === 0 ? (
+                    <div style={{ display: "flex", justifyContent: "center", alignItems: "center", height: "100%", color: "gray" }}>
+                        No messages yet
+                    </div>
+                ) : (
+                    conversation.history.map((message) => (
+                        <div key={`${message.from}:${message.id}`}
+                          className='chat-message'
+                          style={{ display: "flex", flexDirection: "row", gap: "1rem", padding: "10px 0rem" }}
+                        >
+                            <div style={{ paddingLeft: "10px", flexShrink: 0 }}>
+                                <ProfilePicture size="40px" node={message.from} />
+                            </div>
+                            <div style={{ display: "flex", flexDirection: "column", minWidth: 0, gap: "3px" }}>
+                                <div style={{ lineHeight: "1" }}>
+                                    <span
+                                      style={{ marginRight: "8px", fontSize: "0.9rem" }}
+                                      className={getPeerNameColor(peerMap.get(message.from))}
+                                    >
+                                        {message.from}:
+                                    </span>
+                                    <span style={{ color: "#ffffff77", fontSize: "0.7rem" }}>
+                                        {formatTimestamp(message.time_received)}
+                                    </span>
+                                </div>
+                                <span style={{ wordBreak: "break-word", whiteSpace: "pre-wrap", fontSize: "0.9rem" }}>
+                                    {message.contents}
+                                </span>
+                            </div>
+                        </div>
+                    ))
+                )}
+                <div ref={messagesEndRef} style={{height:"1px"}} />
+            </div>
+
+            {/* Chat input */}
+            <div style={{ display: 'flex' }}>
+                <textarea
+                  style={{ flexGrow: 1, resize: "none", margin: '0px' }}
+                  value={messageText}
+                  onChange={(e) => setMessageText(e.target.value)}
+                  onKeyDown={(event) => {
+                      if (event.key === 'Enter' && !event.shiftKey) {
+                          event.preventDefault();
+                          sendMessage();
+                      }
+                  }}
+                />
+                <button style={{ minWidth: '60px', borderRadius: '0px', borderLeft: "0px" }} onClick={sendMessage}>
+                    Send
+                </button>
+            </div>
+        </div>
+    );
+};
+
+export default MessagesGroup;
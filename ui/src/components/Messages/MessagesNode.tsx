@@ -1,6 +1,6 @@
 import React, { useCallback, useEffect, useRef, useState } from 'react';
 import CurrentPageHeader from '../CurrentPageHeader';
-import useDartStore, { MessageStore } from '../../store/dart';
+import useDartStore, { DeliveryStatus, MessageStore } from '../../store/dart';
 import { useNavigate, useParams } from 'react-router-dom';
 import { renderLoading } from '../Middle';
 import ProfilePicture from '../ProfilePicture';
@@ -9,8 +9,31 @@ import { getActivityMessage } from '../Nodes/NodeProfile';
 import { hasUnreadHistory } from './Messages';
 import { dfLinkRegex, dfLinkToRealLink, formatTimestamp, getPeerNameColor, isImageUrl, linkRegex, maybeReplaceWithImage, nodeProfileLink } from '@dartfrog/puddle';
 
+const DeliveryLabel: React.FC<{ status: DeliveryStatus, onRetry: () => void }> = ({ status, onRetry }) => {
+    switch (status.state) {
+      case "Pending":
+        return <span>sending...</span>;
+      case "Delivered":
+        return <span>delivered</span>;
+      case "Read":
+        return <span>read</span>;
+      case "Failed":
+        return (
+          <span>
+            not delivered{' '}
+            <span
+              style={{ textDecoration: "underline", cursor: "pointer" }}
+              onClick={onRetry}
+            >
+              retry
+            </span>
+          </span>
+        );
+    }
+};
+
 const MessagesNode: React.FC = () => {
-    const {setCurrentPage, messageStoreMap, requestNewMessageStore, clearUnreadMessageStore, peerMap, localFwdPeerRequest, requestSendMessage} = useDartStore();
+    const {setCurrentPage, messageStoreMap, requestNewMessageStore, clearUnreadMessageStore, peerMap, localFwdPeerRequest, requestSendMessage, deliveries, requestRetryMessage, typing, requestTyping} = useDartStore();
 
     const { node } = useParams<{ node: string }>();
 
@@ -45,9 +68,26 @@ const MessagesNode: React.FC = () => {
 
     const [chatMessageInputText, setChatMessageInputText] = useState('');
   
+    // the hub forwards at most one typing poke every few seconds anyway
+    const lastTypingSent = useRef(0);
     const handleInputChange = (event) => {
       setChatMessageInputText(event.target.value);
+      if (event.target.value && Date.now() - lastTypingSent.current > 3000) {
+        lastTypingSent.current = Date.now();
+        requestTyping(node);
+      }
     };
+
+    // re-render when the peer's typing indicator runs out
+    const [isPeerTyping, setIsPeerTyping] = useState(false);
+    useEffect(() => {
+      const expiresAt = typing.get(node);
+      const remaining = expiresAt ? expiresAt * 1000 - Date.now() : 0;
+      setIsPeerTyping(remaining > 0);
+      if (remaining <= 0) return;
+      const timer = setTimeout(() => setIsPeerTyping(false), remaining);
+      return () => clearTimeout(timer);
+    }, [typing, node]);
   
     const sendChatCallback = useCallback(
       async (event) => {
@@ -243,6 +283,9 @@ const MessagesNode: React.FC = () => {
                             <div>
                               {peerMap.get(node) && getActivityMessage(peerMap.get(node))}
                             </div>
+                            {isPeerTyping &&
+                              <div>typing...</div>
+                            }
                             <div>
                               {isPinging ? ('pinging...' ):(
                                 <>
@@ -346,6 +389,14 @@ const MessagesNode: React.FC = () => {
                                         }}>
                                             {getMessageInnerText(message.contents)}
                                         </span>
+                                        {deliveries.get(message.id) &&
+                                          <div style={{ color: "#ffffff77", fontSize: "0.7rem", cursor: "default", userSelect: "none", }}>
+                                            <DeliveryLabel
+                                              status={deliveries.get(message.id)}
+                                              onRetry={() => requestRetryMessage(message.id)}
+                                            />
+                                          </div>
+                                        }
                                     </div>
                                 </div>
                             ))
@@ -14,6 +14,7 @@ import NodeProfile from './Nodes/NodeProfile';
 import Messages from './Messages/Messages';
 import CurrentPageHeader from './CurrentPageHeader';
 import MessagesNode from './Messages/MessagesNode';
+import MessagesGroup from './Messages/MessagesGroup';
 import ServicePage from './Services/ServicePage';
 import { useMediaQuery } from 'react-responsive';
 import { Spinner } from '@dartfrog/puddle';
@@ -92,6 +93,9 @@ const Middle: React.FC<MiddleProps> = ({ }) => {
         <Route path="/messages/:node" element={
             renderPage(() => <MessagesNode />, isClientConnected)
         } />
+        <Route path="/messages/group/:conversationId" element={
+            renderPage(() => <MessagesGroup />, isClientConnected)
+        } />
         <Route path="/nodes" element={
             renderPage(() => <Nodes />, isClientConnected)
         } />
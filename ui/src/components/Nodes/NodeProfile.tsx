@@ -1,5 +1,5 @@
 import React, { useState, useEffect, useCallback } from 'react';
-import useDartStore from '../../store/dart';
+import useDartStore, { DirectMessagePrivacy } from '../../store/dart';
 import { Peer, getClassForNameColor, NameColor, Profile, getRecencyText, DEFAULT_PFP, ActivitySetting } from '@dartfrog/puddle';
 import { useNavigate } from 'react-router-dom';
 
@@ -45,7 +45,7 @@ const renderConnectionStatus = (peer: Peer | null, isBadConnection: boolean) =>
 const NodeProfile: React.FC<NodeProps> = ({ }) => {
     const { node } = useParams<{ node: string }>();
 
-    const { peerMap, delPeerMap, localFwdPeerRequest, requestSetProfile, localDeletePeer, setCurrentPage, requestSetActivitySetting } = useDartStore();
+    const { peerMap, delPeerMap, localFwdPeerRequest, requestSetProfile, localDeletePeer, setCurrentPage, requestSetActivitySetting, dmPrivacy, requestSetDmPrivacy } = useDartStore();
 
     const [peer, setPeer] = useState<Peer|null>(null);
     const [profileImage, setProfileImage] = useState<string>(DEFAULT_PFP);
@@ -58,6 +58,7 @@ const NodeProfile: React.FC<NodeProps> = ({ }) => {
     const [selectedNameColor, setSelectedNameColor] = useState<NameColor>(NameColor.Default);
     const [isLoadingImage, setIsLoadingImage] = useState(false);
     const [activitySetting, setActivitySetting] = useState<ActivitySetting>(ActivitySetting.Public);
+    const [selectedDmPrivacy, setSelectedDmPrivacy] = useState<DirectMessagePrivacy>("Public");
 
     const navigate = useNavigate();
 
@@ -99,13 +100,26 @@ const NodeProfile: React.FC<NodeProps> = ({ }) => {
         setActivitySetting(event.target.value as ActivitySetting);
     };
 
+    const handleDmPrivacyChange = (event: React.ChangeEvent<HTMLSelectElement>) => {
+        setSelectedDmPrivacy(event.target.value as DirectMessagePrivacy);
+    };
+
     const handleSave = useCallback(() => {
         setIsEditMode(false);
         const newProfile = new Profile(selectedBio, selectedNameColor, selectedProfileImage)
         requestSetProfile(newProfile);
         requestSetActivitySetting(activitySetting);
+        if (selectedDmPrivacy !== dmPrivacy) {
+          requestSetDmPrivacy(selectedDmPrivacy);
+        }
         localFwdPeerRequest(node);
-    }, [selectedBio, selectedNameColor, selectedProfileImage, activitySetting]);
+    }, [selectedBio, selectedNameColor, selectedProfileImage, activitySetting, selectedDmPrivacy, dmPrivacy]);
+
+    useEffect(() => {
+      if (dmPrivacy) {
+        setSelectedDmPrivacy(dmPrivacy);
+      }
+    }, [dmPrivacy]);
 
 
     useEffect(() => {
@@ -407,6 +421,19 @@ const NodeProfile: React.FC<NodeProps> = ({ }) => {
                                   <option value={ActivitySetting.Public}>share activity</option>
                                   <option value={ActivitySetting.Private}>make activity private</option>
                                 </select>
+                                <select
+                                  name="dmPrivacyOption"
+                                  id="dmPrivacyOption"
+                                  value={selectedDmPrivacy}
+                                  onChange={handleDmPrivacyChange}
+                                  style={{
+                                    width:"auto",
+                                    marginLeft:"1rem",
+                                  }}
+                                >
+                                  <option value="Public">share read receipts and typing</option>
+                                  <option value="Private">hide read receipts and typing</option>
+                                </select>
                               </div>
                             }
                           </div>
@@ -54,6 +54,28 @@ export interface DartStore {
   requestNewMessageStore: (node: string) => void,
   requestSendMessage: (node, text) => void,
   clearUnreadMessageStore: (node) => void,
+  // delivery state of messages we sent, by message id
+  deliveries: Map<string, DeliveryStatus>,
+  requestRetryMessage: (messageId: string) => void,
+  // read receipts and typing indicators, both ways
+  dmPrivacy: DirectMessagePrivacy | null,
+  requestSetDmPrivacy: (privacy: DirectMessagePrivacy) => void,
+  // when each node's typing indicator runs out, in seconds
+  typing: Map<string, number>,
+  requestTyping: (node: string) => void,
+  handleDirectMessagesOutput: (output: any) => void,
+  //
+  groups: Map<string, GroupConversation>,
+  requestCreateGroup: (name: string, members: string[]) => void,
+  requestSendGroupMessage: (conversationId: string, contents: string) => void,
+  requestInviteToGroup: (conversationId: string, node: string) => void,
+  requestLeaveGroup: (conversationId: string) => void,
+  clearUnreadGroup: (conversationId: string) => void,
+  // groups we were added to, by conversation id, until we accept or decline
+  groupInvites: Map<string, GroupInvite>,
+  requestAcceptInvite: (conversationId: string) => void,
+  requestDeclineInvite: (conversationId: string) => void,
+  handleGroupMessagesOutput: (output: any) => void,
   // 
   profile: Profile | null,
   setProfile: (profile) => void,
@@ -286,6 +308,135 @@ const useDartStore = create<DartStore>()(
         }
       })
     },
+    //
+    deliveries: new Map<string, DeliveryStatus>(),
+    requestRetryMessage: (messageId) => {
+      const { api } = get()
+      if (!(api)) return;
+      api.send({data:
+        {
+          "DirectMessages":
+            {
+              "RetryMessage": messageId
+            }
+        }
+      })
+    },
+    dmPrivacy: null,
+    requestSetDmPrivacy: (privacy) => {
+      const { api } = get()
+      if (!(api)) return;
+      api.send({data:
+        {
+          "DirectMessages":
+            {
+              "SetPrivacy": privacy
+            }
+        }
+      })
+    },
+    typing: new Map<string, number>(),
+    requestTyping: (node) => {
+      const { api } = get()
+      if (!(api)) return;
+      api.send({data:
+        {
+          "DirectMessages":
+            {
+              "Typing": node
+            }
+        }
+      })
+    },
+    handleDirectMessagesOutput: (output) => {
+      const { deliveries, typing } = get();
+      if (output["DeliveryList"]) {
+        const newDeliveries = new Map<string, DeliveryStatus>();
+        for (const status of output["DeliveryList"]) {
+          newDeliveries.set(status.message_id, status);
+        }
+        set({ deliveries: newDeliveries });
+      } else if (output["Delivery"]) {
+        const status: DeliveryStatus = output["Delivery"];
+        const newDeliveries = new Map(deliveries);
+        newDeliveries.set(status.message_id, status);
+        set({ deliveries: newDeliveries });
+      } else if (output["DeliveryCleared"]) {
+        // the hub stopped tracking it, so there is no state left to show
+        const newDeliveries = new Map(deliveries);
+        newDeliveries.delete(output["DeliveryCleared"]);
+        set({ deliveries: newDeliveries });
+      } else if (output["Privacy"]) {
+        set({ dmPrivacy: output["Privacy"] });
+      } else if (output["Typing"]) {
+        const { node, expires_at } = output["Typing"];
+        const newTyping = new Map(typing);
+        newTyping.set(node, expires_at);
+        set({ typing: newTyping });
+      } else {
+        console.log("unhandled direct messages update", output)
+      }
+    },
+    //
+    groups: new Map<string, GroupConversation>(),
+    requestCreateGroup: (name, members) => {
+      get().sendPoke({ "GroupMessages": { "CreateGroup": { name, members } } });
+    },
+    requestSendGroupMessage: (conversationId, contents) => {
+      get().sendPoke({ "GroupMessages": { "SendMessage": { conversation_id: conversationId, contents } } });
+    },
+    requestInviteToGroup: (conversationId, node) => {
+      get().sendPoke({ "GroupMessages": { "Invite": { conversation_id: conversationId, node } } });
+    },
+    requestLeaveGroup: (conversationId) => {
+      get().sendPoke({ "GroupMessages": { "Leave": conversationId } });
+    },
+    clearUnreadGroup: (conversationId) => {
+      get().sendPoke({ "GroupMessages": { "ClearUnread": conversationId } });
+    },
+    groupInvites: new Map<string, GroupInvite>(),
+    requestAcceptInvite: (conversationId) => {
+      get().sendPoke({ "GroupMessages": { "AcceptInvite": conversationId } });
+    },
+    requestDeclineInvite: (conversationId) => {
+      get().sendPoke({ "GroupMessages": { "DeclineInvite": conversationId } });
+    },
+    handleGroupMessagesOutput: (output) => {
+      const { groups, groupInvites } = get();
+      if (output["ConversationList"]) {
+        const newGroups = new Map<string, GroupConversation>();
+        for (const conversation of output["ConversationList"]) {
+          newGroups.set(conversation.id, conversation);
+        }
+        set({ groups: newGroups });
+      } else if (output["Conversation"]) {
+        const conversation: GroupConversation = output["Conversation"];
+        const newGroups = new Map(groups);
+        newGroups.set(conversation.id, conversation);
+        set({ groups: newGroups });
+      } else if (output["LeftConversation"]) {
+        const newGroups = new Map(groups);
+        newGroups.delete(output["LeftConversation"]);
+        set({ groups: newGroups });
+      } else if (output["InviteList"]) {
+        const newInvites = new Map<string, GroupInvite>();
+        for (const invite of output["InviteList"]) {
+          newInvites.set(invite.info.id, invite);
+        }
+        set({ groupInvites: newInvites });
+      } else if (output["Invite"]) {
+        const invite: GroupInvite = output["Invite"];
+        const newInvites = new Map(groupInvites);
+        newInvites.set(invite.info.id, invite);
+        set({ groupInvites: newInvites });
+      } else if (output["InviteRemoved"]) {
+        const newInvites = new Map(groupInvites);
+        newInvites.delete(output["InviteRemoved"]);
+        set({ groupInvites: newInvites });
+      } else {
+        console.log("unhandled group messages update", output)
+      }
+    },
    
   })
 )
@@ -305,6 +456,33 @@ export interface DirectMessage {
   time_received: number;
 }
 
+export interface GroupConversation {
+  id: string;
+  name: string;
+  members: string[];
+  history: DirectMessage[];
+}
+
+export interface GroupInvite {
+  info: {
+    id: string;
+    name: string;
+    members: string[];
+  };
+  from: string;
+  received_at: number;
+}
+
+export type DirectMessagePrivacy = "Public" | "Private";
+
+export type DeliveryState = "Pending" | "Delivered" | "Read" | "Failed";
+
+export interface DeliveryStatus {
+  node: string;
+  message_id: string;
+  state: DeliveryState;
+}
+
 export function createMessageStore(peer_node: string): MessageStore {
   return {
     peer_node,